@@ -0,0 +1,63 @@
+//! State that belongs to a Todoist account rather than to a single filter
+use crate::comments::CachedComments;
+use crate::error::Error;
+use crate::pomodoro::Pomodoro;
+use crate::preferences::Preferences;
+use crate::projects::Projects;
+use crate::time::Timezone;
+use crate::tracking::{CurrentTask, TimeEntry};
+use crate::undo::DeletedTask;
+use crate::user::{Stats, User};
+use crate::AppState;
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct AccountState {
+    /// The task on screen and when it was first shown
+    pub current_task: Option<CurrentTask>,
+    pub time_entries: Vec<TimeEntry>,
+    /// Only set while pomodoro mode is on
+    pub pomodoro: Option<Pomodoro>,
+    /// Work intervals spent on each task, keyed by task id
+    pub pomodoro_counts: HashMap<String, u32>,
+    pub user: Option<User>,
+    pub user_updated_at: Option<DateTime<Timezone>>,
+    pub stats: Option<Stats>,
+    pub stats_updated_at: Option<DateTime<Timezone>>,
+    pub preferences: Preferences,
+    pub projects: Option<Projects>,
+    pub projects_updated_at: Option<DateTime<Timezone>>,
+    /// Comments of recently shown tasks, keyed by task id
+    pub comments: HashMap<String, CachedComments>,
+    /// Content of parent tasks outside the filter and when it was fetched, keyed by task id
+    pub parents: HashMap<String, (String, DateTime<Timezone>)>,
+    /// The last deleted task, kept so that the deletion can be undone.
+    /// Boxed as the database stores values inline and this is rarely set.
+    pub deleted: Option<Box<DeletedTask>>,
+    /// Tasks hidden until the given time, keyed by task id
+    pub snoozed: HashMap<String, DateTime<Timezone>>,
+    /// When each task was skipped within the last couple of weeks, keyed by task id
+    pub skips: HashMap<String, Vec<DateTime<Timezone>>>,
+}
+
+pub async fn get_or_create(app_state: &Arc<AppState>, token: &str) -> Result<AccountState, Error> {
+    let account_state = app_state
+        .accounts
+        .begin(false)
+        .await
+        .get(token.to_string())?;
+    Ok(account_state.unwrap_or_default())
+}
+
+pub async fn save(
+    app_state: &Arc<AppState>,
+    token: &str,
+    account_state: AccountState,
+) -> Result<(), Error> {
+    let mut tx = app_state.accounts.begin(true).await;
+    tx.set(token.to_string(), account_state)?;
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Comments on a task, shown below it on the process page
+use crate::error::Error;
+use crate::tasks::Task;
+use crate::time::{self, Timezone};
+use crate::{account, request, AppState};
+use chrono::DateTime;
+use comrak::Options;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const REST_V2_COMMENTS_URL: &str = "/rest/v2/comments";
+const CACHE_COMMENTS_MAX_AGE_MINUTES: i64 = 15;
+
+/// https://developer.todoist.com/rest/v2/#comments
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub id: String,
+    pub content: String,
+    pub posted_at: String,
+    pub attachment: Option<Attachment>,
+}
+
+/// Either an uploaded file or a link, only some fields are set for each
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub resource_type: String,
+    pub file_name: Option<String>,
+    pub file_type: Option<String>,
+    pub file_url: Option<String>,
+    /// Resized copy of an uploaded image
+    pub image: Option<String>,
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Comments fetched for a task, kept until the task's comment count changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedComments {
+    pub comment_count: u32,
+    pub comments: Vec<Comment>,
+    pub updated_at: DateTime<Timezone>,
+}
+
+impl Attachment {
+    pub fn href(&self) -> Option<&str> {
+        self.file_url.as_deref().or(self.url.as_deref())
+    }
+
+    pub fn name(&self) -> &str {
+        self.file_name
+            .as_deref()
+            .or(self.title.as_deref())
+            .or(self.href())
+            .unwrap_or("Attachment")
+    }
+
+    /// Only images get a thumbnail, other files are shown as a link
+    pub fn thumbnail(&self) -> Option<&str> {
+        let is_image = self
+            .file_type
+            .as_deref()
+            .is_some_and(|file_type| file_type.starts_with("image/"));
+
+        match &self.image {
+            Some(image) => Some(image),
+            None if is_image => self.file_url.as_deref(),
+            None => None,
+        }
+    }
+}
+
+/// Fetches from cache or API, ready for display.
+/// None when they could not be fetched, the task is still shown without them.
+pub async fn cached_get_comments(
+    app_state: &Arc<AppState>,
+    token: &str,
+    task: &Task,
+    timezone: &Timezone,
+    test_server_url: &Option<String>,
+) -> Result<Option<Vec<Comment>>, Error> {
+    let comment_count = task.comment_count.unwrap_or_default();
+    if comment_count == 0 {
+        return Ok(Some(Vec::new()));
+    }
+    let account_state = account::get_or_create(app_state, token).await?;
+    if let Some(cached) = account_state.comments.get(&task.id) {
+        if cached.comment_count == comment_count
+            && time::age_in_minutes(cached.updated_at, timezone)? < CACHE_COMMENTS_MAX_AGE_MINUTES
+        {
+            return Ok(Some(cached.comments.clone()));
+        }
+    }
+
+    let comments = match get_comments(token, &task.id, test_server_url)
+        .await
+        .and_then(|comments| for_display(comments, timezone))
+    {
+        Ok(comments) => comments,
+        Err(error) => {
+            tracing::warn!("Could not fetch comments for task {}: {error:?}", task.id);
+            return Ok(None);
+        }
+    };
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+    account_state.comments.retain(|_, cached| {
+        now.signed_duration_since(cached.updated_at).num_minutes() < CACHE_COMMENTS_MAX_AGE_MINUTES
+    });
+    account_state.comments.insert(
+        task.id.clone(),
+        CachedComments {
+            comment_count,
+            comments: comments.clone(),
+            updated_at: now,
+        },
+    );
+    account::save(app_state, token, account_state).await?;
+
+    Ok(Some(comments))
+}
+
+pub async fn get_comments(
+    token: &str,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<Vec<Comment>, Error> {
+    let url = format!("{REST_V2_COMMENTS_URL}?task_id={task_id}");
+    let json = request::get_todoist_rest(token, &url, test_server_url.clone()).await?;
+    rest_json_to_comments(json)
+}
+
+pub fn rest_json_to_comments(json: String) -> Result<Vec<Comment>, Error> {
+    let comments: Vec<Comment> = serde_json::from_str(&json)?;
+    Ok(comments)
+}
+
+/// Renders the content as Markdown and the posting time in the user's timezone
+pub fn for_display(comments: Vec<Comment>, timezone: &Timezone) -> Result<Vec<Comment>, Error> {
+    let options = Options::default();
+    comments
+        .into_iter()
+        .map(|c| {
+            let posted_at = DateTime::parse_from_rfc3339(&c.posted_at)?.with_timezone(timezone);
+            Ok(Comment {
+                content: comrak::markdown_to_html(&c.content, &options),
+                posted_at: posted_at.format("%Y-%m-%d %H:%M").to_string(),
+                ..c
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::ResponseFromFile;
+    use chrono_tz::Tz;
+
+    #[tokio::test]
+    async fn test_for_display() {
+        let comments = rest_json_to_comments(ResponseFromFile::Comments.read().await).unwrap();
+        let comments = for_display(comments, &Timezone::from(Tz::America__Vancouver)).unwrap();
+
+        assert_eq!(
+            comments[0].content,
+            "<p>Filters are in the <strong>cupboard</strong> above the fridge</p>\n"
+        );
+        assert_eq!(comments[0].posted_at, "2024-05-13 10:45");
+        assert!(comments[0].attachment.is_none());
+
+        let image = comments[1].attachment.as_ref().unwrap();
+        assert_eq!(
+            image.thumbnail(),
+            Some("https://files.todoist.com/filter_large.jpg")
+        );
+        assert_eq!(image.name(), "filter.jpg");
+
+        let pdf = comments[2].attachment.as_ref().unwrap();
+        assert_eq!(pdf.thumbnail(), None);
+        assert_eq!(pdf.href(), Some("https://files.todoist.com/manual.pdf"));
+    }
+}
@@ -1,43 +1,225 @@
+use crate::unsplash::{self, Unsplash};
+use crate::Link;
+use askama::Template;
+use axum::extract::Request;
+use axum::http::header::ACCEPT;
 use axum::http::StatusCode;
-use axum::response::{IntoResponse, Response};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
 use std::num::ParseIntError;
 use tokio::task::JoinError;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Error {
-    pub message: String,
-    pub source: String,
-    pub code: StatusCode,
+pub enum Error {
+    /// Todoist rejected the API token (401 or 403)
+    InvalidToken {
+        source: String,
+        message: String,
+    },
+    /// Todoist could not parse a filter
+    BadFilter {
+        source: String,
+        message: String,
+    },
+    /// The request to us or to an API was malformed
+    BadRequest {
+        source: String,
+        message: String,
+    },
+    /// An API returned 404, usually a task that was deleted or completed elsewhere
+    NotFound {
+        source: String,
+        message: String,
+    },
+    /// An API returned 429
+    RateLimited {
+        source: String,
+        message: String,
+    },
+    /// An API could not be reached or returned a 5xx
+    UpstreamDown {
+        source: String,
+        message: String,
+    },
+    Internal {
+        source: String,
+        message: String,
+    },
 }
 
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    title: String,
+    navigation: Vec<Link>,
+    unsplash: Unsplash,
+    explanation: String,
+    fix: Option<Link>,
+}
+
+impl Error {
+    pub fn code(&self) -> StatusCode {
+        match self {
+            Error::InvalidToken { .. } => StatusCode::UNAUTHORIZED,
+            Error::BadFilter { .. } => StatusCode::BAD_REQUEST,
+            Error::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Error::NotFound { .. } => StatusCode::NOT_FOUND,
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::UpstreamDown { .. } => StatusCode::BAD_GATEWAY,
+            Error::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            Error::InvalidToken { source, .. }
+            | Error::BadFilter { source, .. }
+            | Error::BadRequest { source, .. }
+            | Error::NotFound { source, .. }
+            | Error::RateLimited { source, .. }
+            | Error::UpstreamDown { source, .. }
+            | Error::Internal { source, .. } => source,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::InvalidToken { message, .. }
+            | Error::BadFilter { message, .. }
+            | Error::BadRequest { message, .. }
+            | Error::NotFound { message, .. }
+            | Error::RateLimited { message, .. }
+            | Error::UpstreamDown { message, .. }
+            | Error::Internal { message, .. } => message,
+        }
+    }
+
+    /// Machine readable name used in JSON responses
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InvalidToken { .. } => "invalid_token",
+            Error::BadFilter { .. } => "bad_filter",
+            Error::BadRequest { .. } => "bad_request",
+            Error::NotFound { .. } => "not_found",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::UpstreamDown { .. } => "upstream_down",
+            Error::Internal { .. } => "internal",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Error::InvalidToken { .. } => "Invalid API token",
+            Error::BadFilter { .. } => "Invalid filter",
+            Error::BadRequest { .. } => "Bad request",
+            Error::NotFound { .. } => "Not found",
+            Error::RateLimited { .. } => "Too many requests",
+            Error::UpstreamDown { .. } => "Todoist is unavailable",
+            Error::Internal { .. } => "Something went wrong",
+        }
+    }
+
+    fn explanation(&self) -> &'static str {
+        match self {
+            Error::InvalidToken { .. } => {
+                "Todoist did not accept your API token. It may have been mistyped or revoked."
+            }
+            Error::BadFilter { .. } => "Todoist could not understand one of your filters.",
+            Error::BadRequest { .. } => "The request was missing some information.",
+            Error::NotFound { .. } => {
+                "Todoist could not find it. It may have been deleted or completed elsewhere."
+            }
+            Error::RateLimited { .. } => {
+                "Todoist is limiting how often we can make requests. Wait a minute and try again."
+            }
+            Error::UpstreamDown { .. } => {
+                "We could not reach Todoist or it returned an error. Try again shortly."
+            }
+            Error::Internal { .. } => "An unexpected error occurred.",
+        }
+    }
+
+    fn fix(&self) -> Option<Link> {
+        match self {
+            Error::InvalidToken { .. } => Some(Link {
+                name: "Re-enter API token".into(),
+                href: "/".into(),
+            }),
+            Error::BadFilter { .. } | Error::BadRequest { .. } => Some(Link {
+                name: "Edit filter".into(),
+                href: "/".into(),
+            }),
+            Error::NotFound { .. } => Some(Link {
+                name: "Back to your tasks".into(),
+                href: "/".into(),
+            }),
+            Error::RateLimited { .. } | Error::UpstreamDown { .. } | Error::Internal { .. } => None,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "error": self.kind(),
+            "source": self.source(),
+            "message": self.explanation(),
+        })
+    }
+}
+
+/// The details can include upstream requests and responses, so they are logged rather than shown
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        let Self {
-            code,
-            source,
-            message,
-        } = self;
-        (code, format!("{source}: {message}")).into_response()
+        let code = self.code();
+        tracing::error!("{} {}: {}", code, self.source(), self.message());
+        let template = ErrorTemplate {
+            title: self.title().into(),
+            navigation: crate::get_nav(),
+            unsplash: unsplash::stub(),
+            explanation: self.explanation().into(),
+            fix: self.fix(),
+        };
+
+        let mut response = match template.render() {
+            Ok(html) => (code, Html(html)).into_response(),
+            Err(_) => (code, template.explanation).into_response(),
+        };
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Middleware that swaps the HTML error page for JSON when the client asks for it
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let wants_json = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    let response = next.run(request).await;
+
+    match response.extensions().get::<Error>() {
+        Some(error) if wants_json => (error.code(), Json(error.to_json())).into_response(),
+        _ => response,
     }
 }
 
 impl From<askama::Error> for Error {
     fn from(value: askama::Error) -> Self {
         match value {
-            askama::Error::Fmt => Self {
+            askama::Error::Fmt => Self::Internal {
                 source: String::from("askama_axum"),
                 message: "Unknown formatting error".to_string(),
-                code: StatusCode::INTERNAL_SERVER_ERROR,
             },
-            askama::Error::Custom(error) => Self {
+            askama::Error::Custom(error) => Self::Internal {
                 source: String::from("askama_axum"),
                 message: format!("Error on ? in template {error}"),
-                code: StatusCode::INTERNAL_SERVER_ERROR,
             },
-            _ => Self {
+            _ => Self::Internal {
                 source: String::from("askama_axum"),
                 message: value.to_string(),
-                code: StatusCode::INTERNAL_SERVER_ERROR,
             },
         }
     }
@@ -45,98 +227,134 @@ impl From<askama::Error> for Error {
 
 impl From<echodb::Error> for Error {
     fn from(value: echodb::Error) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("echodb"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
-impl From<chrono::LocalResult<chrono::DateTime<chrono_tz::Tz>>> for Error {
-    fn from(value: chrono::LocalResult<chrono::DateTime<chrono_tz::Tz>>) -> Self {
-        Self {
+impl From<chrono::LocalResult<chrono::DateTime<crate::time::Timezone>>> for Error {
+    fn from(value: chrono::LocalResult<chrono::DateTime<crate::time::Timezone>>) -> Self {
+        Self::Internal {
             source: String::from("chrono"),
             message: format!("{value:?}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<tokio::sync::mpsc::error::SendError<Error>> for Error {
     fn from(value: tokio::sync::mpsc::error::SendError<Error>) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("tokio mpsc"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<chrono_tz::ParseError> for Error {
     fn from(value: chrono_tz::ParseError) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("chrono_tz"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<JoinError> for Error {
     fn from(value: JoinError) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("tokio JoinError"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<ParseIntError> for Error {
     fn from(value: ParseIntError) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("ParseIntError"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<chrono::ParseError> for Error {
     fn from(value: chrono::ParseError) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("chrono"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
-        Self {
+        Self::Internal {
             source: String::from("serde_json"),
             message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 impl From<reqwest::Error> for Error {
     fn from(value: reqwest::Error) -> Self {
-        Self {
-            source: String::from("reqwest"),
-            message: format!("{value}"),
-            code: StatusCode::INTERNAL_SERVER_ERROR,
+        let source = String::from("reqwest");
+        let message = format!("{value}");
+        if value.is_connect() || value.is_timeout() {
+            Self::UpstreamDown { source, message }
+        } else {
+            Self::Internal { source, message }
         }
     }
 }
 
 pub fn new(source: &str, message: &str) -> Error {
-    Error {
+    Error::Internal {
         source: String::from(source),
         message: String::from(message),
-        code: StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Classifies a failed API response by its status code
+pub fn from_status(status: StatusCode, source: &str, message: &str) -> Error {
+    let source = String::from(source);
+    let message = String::from(message);
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::InvalidToken { source, message },
+        StatusCode::BAD_REQUEST => Error::BadRequest { source, message },
+        StatusCode::NOT_FOUND => Error::NotFound { source, message },
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited { source, message },
+        status if status.is_server_error() => Error::UpstreamDown { source, message },
+        _ => Error::Internal { source, message },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert_eq!(
+            from_status(StatusCode::UNAUTHORIZED, "reqwest", "nope").code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            from_status(StatusCode::TOO_MANY_REQUESTS, "reqwest", "slow down").kind(),
+            "rate_limited"
+        );
+        assert_eq!(
+            from_status(StatusCode::SERVICE_UNAVAILABLE, "reqwest", "down").kind(),
+            "upstream_down"
+        );
+        assert_eq!(
+            from_status(StatusCode::NOT_FOUND, "reqwest", "missing").kind(),
+            "not_found"
+        );
+        assert_eq!(
+            from_status(StatusCode::CONFLICT, "reqwest", "conflict").kind(),
+            "internal"
+        );
     }
 }
@@ -1,23 +1,35 @@
 use std::sync::Arc;
 
-use axum::Router;
+use account::AccountState;
+use axum::{middleware, Router};
 use chrono::DateTime;
-use chrono_tz::Tz;
 use echodb::Database;
 use serde::Serialize;
+use session::Session;
 use shuttle_runtime::SecretStore;
 use std::str::FromStr;
 use strum::EnumString;
-use tasks::Task;
+use tasks::{Order, Task};
+use time::Timezone;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tracing::Level;
 use unsplash::Unsplash;
 
+mod account;
+mod comments;
 mod error;
+mod pomodoro;
+mod preferences;
+mod projects;
 mod request;
 mod responses;
+mod session;
+mod skips;
+mod snooze;
 mod tasks;
 mod time;
+mod tracking;
+mod undo;
 mod unsplash;
 mod user;
 mod views;
@@ -27,6 +39,8 @@ const ENV: &str = "ENV";
 
 struct AppState {
     db: Database<String, UserState>,
+    /// Keyed by Todoist token
+    accounts: Database<String, AccountState>,
     unsplash_api_key: String,
     env: Env,
     test_server_url: Option<String>,
@@ -43,10 +57,18 @@ enum Env {
 struct UserState {
     tasks: Vec<Task>,
     skip_task_ids: Vec<String>,
-    tasks_updated_at: Option<DateTime<Tz>>,
+    tasks_updated_at: Option<DateTime<Timezone>>,
     unsplash: Option<Unsplash>,
-    unsplash_updated_at: Option<DateTime<Tz>>,
-    timezone: Option<Tz>,
+    unsplash_updated_at: Option<DateTime<Timezone>>,
+    /// Chosen with the timezone parameter, wins over the preference
+    timezone: Option<Timezone>,
+    session: Option<Session>,
+    /// Falls back to the account preference when not set
+    order: Option<Order>,
+    /// Hold back tasks until their due time arrives
+    hide_future: bool,
+    /// Process subtasks before their parent instead of as a checklist under it
+    children_first: bool,
 }
 
 #[derive(Serialize)]
@@ -60,16 +82,23 @@ fn routes(app_state: Arc<AppState>) -> Router {
         // Routes
         .merge(views::index::routes(app_state.clone()))
         .merge(views::shortcuts::routes(app_state.clone()))
+        .merge(views::preview::routes(app_state.clone()))
+        .merge(views::summary::routes(app_state.clone()))
+        .merge(views::history::routes(app_state.clone()))
+        .merge(views::settings::routes(app_state.clone()))
         .merge(views::process::routes(app_state))
+        .layer(middleware::from_fn(error::negotiate))
 }
 
 #[shuttle_runtime::main]
 async fn main(#[shuttle_runtime::Secrets] secrets: SecretStore) -> shuttle_axum::ShuttleAxum {
     let db = echodb::new::<String, UserState>();
+    let accounts = echodb::new::<String, AccountState>();
     let unsplash_api_key = secrets.get(UNSPLASH_API_KEY).expect(UNSPLASH_API_KEY);
     let env = secrets.get(ENV).expect(ENV);
     let app_state = Arc::new(AppState {
         db,
+        accounts,
         unsplash_api_key,
         env: Env::from_str(&env).unwrap(),
         test_server_url: None,
@@ -103,12 +132,94 @@ mod tests {
 
     use super::*;
     use axum_test::TestServer;
+    use mockito::{Matcher, Mock, ServerGuard};
+
+    fn app_state(url: &str) -> Arc<AppState> {
+        Arc::new(AppState {
+            db: echodb::new::<String, UserState>(),
+            accounts: echodb::new::<String, AccountState>(),
+            unsplash_api_key: "123".to_string(),
+            env: Env::Test,
+            test_server_url: Some(url.to_string()),
+        })
+    }
+
+    /// What the process page fetches besides the tasks
+    struct ProcessMocks {
+        user: Mock,
+        projects: Mock,
+        stats: Mock,
+    }
+
+    impl ProcessMocks {
+        fn assert(&self) {
+            self.user.assert();
+            self.projects.assert();
+            self.stats.assert();
+        }
+    }
+
+    async fn mock_process_defaults(server: &mut ServerGuard) -> ProcessMocks {
+        let user = server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::Regex(r#"\["user"\]"#.to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(ResponseFromFile::Sync.read().await)
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        let projects = server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::Regex("projects".to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(ResponseFromFile::Projects.read().await)
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        let stats = server
+            .mock("GET", "/sync/v9/completed/get_stats")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(ResponseFromFile::Stats.read().await)
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        ProcessMocks {
+            user,
+            projects,
+            stats,
+        }
+    }
+
+    /// Tasks for the #checklist filter, create it once the expectations are set
+    fn mock_tasks(server: &mut ServerGuard, body: &str) -> Mock {
+        server
+            .mock("GET", "/rest/v2/tasks/?filter=%23checklist")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+    }
+
+    /// A sync command, told apart from the others by its body
+    async fn mock_command(server: &mut ServerGuard, pattern: &str) -> Mock {
+        server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::Regex(pattern.to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(ResponseFromFile::Sync.read().await)
+            .create_async()
+            .await
+    }
 
     #[tokio::test]
     async fn test_home() {
         let db = echodb::new::<String, UserState>();
         let app_state = Arc::new(AppState {
             db,
+            accounts: echodb::new::<String, AccountState>(),
             unsplash_api_key: "123".to_string(),
             env: Env::Test,
             test_server_url: None,
@@ -124,7 +235,6 @@ mod tests {
 
     #[tokio::test]
     async fn test_process() {
-        let db = echodb::new::<String, UserState>();
         let mut server = mockito::Server::new_async().await;
         let url = "/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles";
         let mock = server
@@ -132,28 +242,924 @@ mod tests {
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(ResponseFromFile::Sync.read().await)
+            .expect_at_least(1)
             .create_async()
             .await;
-        let mock2 = server
-            .mock("GET", "/rest/v2/tasks/?filter=%23checklist")
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let text = "Change water filter under sink";
+
+        let response = server.get(url).await;
+        assert!(response.text().contains(text));
+        mock.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_progress() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        let text = response.text();
+        assert!(text.contains("0 done, 0 skipped, 1 remaining"));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_goals() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        let text = response.text();
+        assert!(text.contains("12 day streak"));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_due() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        let text = response.text();
+        // The fixture is a recurring task that is past due
+        assert!(text.contains("every 3 months"));
+        assert!(text.contains("days overdue"));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_capture() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let capture_mock = server
+            .mock("POST", "/sync/v9/quick/add")
+            .match_body(Matcher::Regex("Buy milk tomorrow #Errands".to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body("{}")
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        // Captured tasks go to Todoist without refreshing the queue
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&capture=Buy%20milk%20tomorrow%20%23Errands")
+            .await;
+        let text = response.text();
+        assert!(text.contains(r#"Added "Buy milk tomorrow #Errands" to Todoist"#));
+        assert!(text.contains("Change water filter under sink"));
+        capture_mock.assert();
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_due_time() {
+        let mut server = mockito::Server::new_async().await;
+        let mut tasks: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Tasks.read().await).unwrap();
+        tasks[0]["due"]["date"] = "2099-05-14".into();
+        tasks[0]["due"]["datetime"] = "2099-05-14T17:30:00.000000".into();
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &tasks.to_string())
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response.text().contains("2099-05-14 17:30"));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_without_stats() {
+        let mut server = mockito::Server::new_async().await;
+        let user_mock = mock_command(&mut server, r#"\["user"\]"#).await;
+        let projects_mock = server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::Regex("projects".to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(ResponseFromFile::Projects.read().await)
+            .create_async()
+            .await;
+        let stats_mock = server
+            .mock("GET", "/sync/v9/completed/get_stats")
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .create_async()
+            .await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        response.assert_status_ok();
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(!text.contains("daily goal"));
+        user_mock.assert();
+        projects_mock.assert();
+        stats_mock.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_comments() {
+        let mut server = mockito::Server::new_async().await;
+        let url = "/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles";
+        let tasks = ResponseFromFile::Tasks
+            .read()
+            .await
+            .replace(r#""comment_count": 0"#, r#""comment_count": 3"#);
+        let mut comments: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Comments.read().await).unwrap();
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &tasks).create_async().await;
+        // Cached until a comment is added
+        let mock3 = server
+            .mock("GET", "/rest/v2/comments?task_id=7662880639")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(comments.to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let mut added = comments[0].clone();
+        added["id"] = "2992679863".into();
+        added["content"] = "Waiting on Bob".into();
+        comments.as_array_mut().unwrap().push(added);
+        let mock4 = server
+            .mock("GET", "/rest/v2/comments?task_id=7662880639")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(comments.to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let note_mock = mock_command(&mut server, "Waiting on Bob.*7662880639.*note_add").await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        for _ in 0..2 {
+            let response = server.get(url).await;
+            let text = response.text();
+            assert!(text.contains("3 comments"));
+            assert!(text.contains("<strong>cupboard</strong>"));
+            assert!(text.contains(r#"<img src="https://files.todoist.com/filter_large.jpg""#));
+            assert!(text.contains("manual.pdf"));
+        }
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&comment_task_id=7662880639&comment=Waiting%20on%20Bob")
+            .await;
+        let text = response.text();
+        assert!(text.contains("4 comments"));
+        assert!(text.contains("Waiting on Bob"));
+        mock2.assert();
+        note_mock.assert();
+        mock3.assert();
+        mock4.assert();
+        defaults.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_comments_unavailable() {
+        let mut server = mockito::Server::new_async().await;
+        let tasks = ResponseFromFile::Tasks
+            .read()
+            .await
+            .replace(r#""comment_count": 0"#, r#""comment_count": 3"#);
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &tasks).create_async().await;
+        let mock3 = server
+            .mock("GET", "/rest/v2/comments?task_id=7662880639")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        response.assert_status_ok();
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(text.contains("Comments could not be loaded"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_edit() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let update_mock = mock_command(
+            &mut server,
+            r#"Replace \*\*both\*\* filters.*"labels":\["physical","errand"\].*"priority":4.*item_update"#,
+        )
+        .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response.text().contains("has-text-white is-size-5"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&edit_task_id=7662880639&content=Replace%20**both**%20filters&description=&priority=4&labels=physical%2C%20errand")
+            .await;
+        let text = response.text();
+        assert!(text.contains("Replace <strong>both</strong> filters"));
+        assert!(text.contains("has-text-danger is-size-5"));
+        assert!(text.contains(r#"<span class="tag">errand</span>"#));
+        defaults.assert();
+        mock2.assert();
+        update_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_move() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        // Once moved the task no longer matches the filter
+        let mock3 = mock_tasks(&mut server, "[]")
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        let move_mock = mock_command(
+            &mut server,
+            r#""id":"7662880639","section_id":"159732004".*item_move"#,
+        )
+        .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        let text = response.text();
+        assert!(
+            text.contains(r#"<option value="section:159732004">Household › Maintenance</option>"#)
+        );
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&move_task_id=7662880639&move_to=section%3A159732004")
+            .await;
+        assert!(response.text().contains("You are all caught up!"));
+
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        assert!(response
+            .text()
+            .contains("Moved: Change water filter under sink and in jug"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+        move_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_move_within_filter() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        // The section is part of the filter so the task stays
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let move_mock = mock_command(
+            &mut server,
+            r#""id":"7662880639","section_id":"159732004".*item_move"#,
+        )
+        .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&move_task_id=7662880639&move_to=section%3A159732004")
+            .await;
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(text.contains("0 done, 0 skipped, 1 remaining"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&move_task_id=7662880639")
+            .await;
+        response.assert_status_bad_request();
+
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        assert!(!response.text().contains("Moved:"));
+        defaults.assert();
+        mock2.assert();
+        move_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_follow_up() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let mock3 = mock_tasks(&mut server, "[]").create_async().await;
+        let complete_mock = mock_command(&mut server, "7662880639.*item_close").await;
+        let follow_up_mock = mock_command(
+            &mut server,
+            r#""content":"Buy more filters","description":"Follow-up to \[Change water filter under sink and in jug\]\(https://app.todoist.com/app/task/7662880639\)","due":\{"string":"next month"\},"project_id":"2321744921".*item_add"#,
+        )
+        .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response
+            .text()
+            .contains(r#"value="Follow up on Change water filter under sink and in jug""#));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&complete_task_id=7662880639&follow_up=Buy%20more%20filters&follow_up_due=next%20month")
+            .await;
+        assert!(response.text().contains("You are all caught up!"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+        complete_mock.assert();
+        follow_up_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_subtasks() {
+        let mut server = mockito::Server::new_async().await;
+        let mut tasks: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Tasks.read().await).unwrap();
+        let mut subtask = tasks[0].clone();
+        subtask["id"] = "7662880640".into();
+        subtask["content"] = "Rinse the jug".into();
+        subtask["parent_id"] = "7662880639".into();
+        tasks.as_array_mut().unwrap().push(subtask);
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &tasks.to_string())
+            .create_async()
+            .await;
+        let complete_mock = mock_command(&mut server, "7662880640.*item_close").await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        // The parent waits until its subtask is done
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles&children_first=on")
+            .await;
+        let text = response.text();
+        assert!(text.contains(r#"has-text-weight-semibold"><p>Rinse the jug</p>"#));
+        assert!(text.contains("› Change water filter under sink and in jug"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&children_first=off")
+            .await;
+        let text = response.text();
+        assert!(text.contains(r#"has-text-weight-semibold"><p>Change water filter"#));
+        assert!(text.contains("1 open subtask"));
+        assert!(text.contains(r#"name="complete_subtask_id" value="7662880640""#));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&complete_subtask_id=7662880640")
+            .await;
+        let text = response.text();
+        assert!(text.contains(r#"has-text-weight-semibold"><p>Change water filter"#));
+        assert!(!text.contains("Rinse the jug"));
+        defaults.assert();
+        mock2.assert();
+        complete_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_parent() {
+        let mut server = mockito::Server::new_async().await;
+        let mut tasks: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Tasks.read().await).unwrap();
+        tasks[0]["parent_id"] = "7662880600".into();
+        let mut parent = tasks[0].clone();
+        parent["id"] = "7662880600".into();
+        parent["content"] = "Look after the **kitchen**".into();
+        parent["parent_id"] = serde_json::Value::Null;
+        let mut orphan = tasks.clone();
+        orphan[0]["parent_id"] = "404".into();
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &tasks.to_string())
+            .create_async()
+            .await;
+        let mock3 = server
+            .mock("GET", "/rest/v2/tasks/?filter=today")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(orphan.to_string())
+            .create_async()
+            .await;
+        // Fetched once as the parent is not part of the filter
+        let parent_mock = server
+            .mock("GET", "/rest/v2/tasks/7662880600")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(parent.to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let missing_mock = server
+            .mock("GET", "/rest/v2/tasks/404")
+            .with_status(404)
+            .with_body("Task not found")
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        for _ in 0..2 {
+            let response = server
+                .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+                .await;
+            let text = response.text();
+            assert!(text.contains("Household"));
+            assert!(text.contains("Look after the <strong>kitchen</strong>"));
+        }
+
+        // A parent that cannot be fetched is left out
+        let response = server
+            .get("/process?token=xxxx&filter=today&timezone=America%2FLos_Angeles")
+            .await;
+        response.assert_status_ok();
+        assert!(response.text().contains("Change water filter under sink"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+        parent_mock.assert();
+        missing_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_snooze() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        // Fetched again once every cached task is snoozed
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let mock3 = server
+            .mock("GET", "/rest/v2/tasks/?filter=today")
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(ResponseFromFile::Tasks.read().await)
             .create_async()
             .await;
-        let app_state = Arc::new(AppState {
-            db,
-            unsplash_api_key: "123".to_string(),
-            env: Env::Test,
-            test_server_url: Some(server.url()),
-        });
-        let server = TestServer::new(routes(app_state)).unwrap();
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
 
-        let text = "Change water filter under sink";
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response.text().contains(r#"value="one_hour""#));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&snooze_task_id=7662880639&snooze_for=one_hour")
+            .await;
+        assert!(response.text().contains("You are all caught up!"));
+
+        // Snoozes are per account, so other filters hide the task too
+        let response = server
+            .get("/process?token=xxxx&filter=today&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response.text().contains("You are all caught up!"));
+
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        assert!(response
+            .text()
+            .contains("Snoozed: Change water filter under sink and in jug"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_revisit_skipped() {
+        let mut server = mockito::Server::new_async().await;
+        let mut tasks: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Tasks.read().await).unwrap();
+        let mut second = tasks[0].clone();
+        second["id"] = "7662880640".into();
+        second["content"] = "Descale the kettle".into();
+        tasks.as_array_mut().unwrap().push(second);
+        let defaults = mock_process_defaults(&mut server).await;
+        // Fetched again once every cached task is skipped, the skips are kept
+        let mock2 = mock_tasks(&mut server, &tasks.to_string())
+            .expect(2)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        server
+            .get("/settings?token=xxxx&save=true&timezone=America%2FLos_Angeles&skip=revisit")
+            .await;
+        let response = server.get("/process?token=xxxx&filter=%23checklist").await;
+        assert!(response.text().contains("Change water filter under sink"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&skip_task_id=7662880639")
+            .await;
+        assert!(response.text().contains("Descale the kettle"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&skip_task_id=7662880640")
+            .await;
+        let text = response.text();
+        assert!(text.contains("You are all caught up!"));
+        assert!(text.contains("2 skipped tasks are waiting."));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&revisit_skipped=on")
+            .await;
+        assert!(response.text().contains("Change water filter under sink"));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_skip_alert() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        // Skipping the only task empties the queue, so it is fetched again each time
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(6)
+            .create_async()
+            .await;
+        let mock3 = mock_tasks(&mut server, "[]").create_async().await;
+        let postpone_mock = mock_command(
+            &mut server,
+            r#""due":\{"date":"\d{4}-\d{2}-\d{2}","is_recurring":true,"string":"every 3 months"\},"id":"7662880639".*item_update"#,
+        )
+        .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        for _ in 0..5 {
+            let response = server
+                .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+                .await;
+            assert!(!response.text().contains("Skipped 5 times"));
+            let response = server
+                .get("/process?token=xxxx&filter=%23checklist&skip_task_id=7662880639")
+                .await;
+            assert!(response.text().contains("You are all caught up!"));
+        }
+        let response = server.get("/process?token=xxxx&filter=%23checklist").await;
+        let text = response.text();
+        assert!(text.contains("Skipped 5 times today"));
+        assert!(text.contains(r#"value="Postpone in 1 month""#));
+        // Deleting goes through the usual confirmation
+        assert!(text.contains(r#"id="skipalertdelete""#));
+        assert_eq!(text.matches(r#"name="delete_task_id""#).count(), 1);
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&postpone_task_id=7662880639")
+            .await;
+        assert!(response.text().contains("You are all caught up!"));
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        assert!(response
+            .text()
+            .contains("Postponed: Change water filter under sink and in jug"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+        postpone_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_delete() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mut project_tasks: serde_json::Value =
+            serde_json::from_str(&ResponseFromFile::Tasks.read().await).unwrap();
+        let mut subtask = project_tasks[0].clone();
+        subtask["id"] = "7662880640".into();
+        subtask["content"] = "Rinse the jug".into();
+        subtask["parent_id"] = "7662880639".into();
+        project_tasks.as_array_mut().unwrap().push(subtask);
+        let mock2 = mock_tasks(&mut server, &project_tasks.to_string())
+            .create_async()
+            .await;
+        // Once deleted the task is no longer returned
+        let mock3 = mock_tasks(&mut server, "[]").create_async().await;
+        let project_mock = server
+            .mock("GET", "/rest/v2/tasks/?project_id=2321744921")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(project_tasks.to_string())
+            .create_async()
+            .await;
+        let delete_mock = mock_command(&mut server, r#""id":"7662880639".*item_delete"#).await;
+        // The subtask is nested under the temp id of the recreated parent
+        let add_mock = server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(
+                    r#"Change water filter.*"string":"every 3 months".*item_add"#.to_string(),
+                ),
+                Matcher::Regex(
+                    r#"Rinse the jug.*"parent_id":"[0-9a-f]{8}-[0-9a-f-]{27}".*item_add"#
+                        .to_string(),
+                ),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(|request| {
+                let body: serde_json::Value =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let temp_id = |index: usize| {
+                    body["commands"][index]["temp_id"]
+                        .as_str()
+                        .unwrap()
+                        .to_string()
+                };
+                serde_json::json!({"temp_id_mapping": {
+                    temp_id(0): "7662880700",
+                    temp_id(1): "7662880701",
+                }})
+                .to_string()
+                .into_bytes()
+            })
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=America%2FLos_Angeles")
+            .await;
+        assert!(response.text().contains("Yes, delete"));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&delete_task_id=7662880639")
+            .await;
+        let text = response.text();
+        assert!(text.contains("You are all caught up!"));
+        assert!(text.contains(r#"name="undo_delete" value="7662880639""#));
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&undo_delete=7662880639")
+            .await;
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(text.contains(r#"value="7662880700""#));
+        // The subtask is back under its recreated parent
+        assert!(text.contains(r#"name="complete_subtask_id" value="7662880701""#));
+        assert!(!text.contains("undo_delete"));
+        defaults.assert();
+        mock2.assert();
+        mock3.assert();
+        project_mock.assert();
+        delete_mock.assert();
+        add_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_invalid_token() {
+        let mut server = mockito::Server::new_async().await;
+        let url = "/process?token=xxxx&filter=%23checklist";
+        let mock = server
+            .mock("POST", "/sync/v9/sync")
+            .with_status(401)
+            .with_body("Unauthorized")
+            .expect(2)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
 
         let response = server.get(url).await;
-        assert!(response.text().contains(text));
+        response.assert_status_unauthorized();
+        let text = response.text();
+        assert!(text.contains("Re-enter API token"));
+        // The upstream response is logged, not shown
+        assert!(!text.contains("Unauthorized"));
+
+        let response = server
+            .get(url)
+            .add_header("accept", "application/json")
+            .await;
+        response.assert_status_unauthorized();
+        assert!(!response.text().contains("Unauthorized"));
+        let json = response.json::<serde_json::Value>();
+        assert_eq!(json["error"], "invalid_token");
         mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_preview() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let mock2 = server
+            .mock("GET", "/rest/v2/tasks/?filter=bad")
+            .with_status(400)
+            .with_body("Invalid filter")
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server.get("/preview?token=xxxx&filter=%23checklist").await;
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(text.contains("Start focus session"));
+
+        let response = server
+            .get("/preview?token=xxxx&filter=%23checklist,bad")
+            .await;
+        let text = response.text();
+        assert!(text.contains("Invalid filter"));
+        assert!(!text.contains("response:"));
+        assert!(!text.contains("Start focus session"));
+        mock.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_session_summary() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let complete_mock = mock_command(&mut server, "7662880639.*item_close").await;
+        let mock3 = mock_command(&mut server, "SingleTask: spent 0m on .*Jug too.*note_add").await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&pomodoro=on")
+            .await;
+        assert!(response.text().contains("left in this interval"));
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&complete_task_id=7662880639&log_time=on&notes=Jug%20too")
+            .await;
+        let text = response.text();
+        assert!(text.contains("You are all caught up!"));
+        assert!(text.contains("1 completed, 0 skipped"));
+
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        assert!(response
+            .text()
+            .contains("Completed: Change water filter under sink and in jug"));
+
+        let response = server.get("/history?token=xxxx").await;
+        assert!(response.text().contains("Household"));
+        defaults.assert();
+        mock2.assert();
+        complete_mock.assert();
+        mock3.assert();
+    }
+
+    #[tokio::test]
+    async fn test_process_timezone() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        server
+            .get("/settings?token=xxxx&save=true&timezone=Asia%2FKolkata")
+            .await;
+        server
+            .get("/process?token=xxxx&filter=%23checklist&timezone=UTC")
+            .await;
+        // The choice is kept once the parameter is gone from the URL
+        server
+            .get("/process?token=xxxx&filter=%23checklist&skip_task_id=7662880639")
+            .await;
+
+        let response = server
+            .get("/summary.md?token=xxxx&filter=%23checklist")
+            .await;
+        let text = response.text();
+        let skipped_at = text
+            .lines()
+            .find(|l| l.ends_with("Skipped: Change water filter under sink and in jug"))
+            .map(|l| l[2..7].to_string())
+            .unwrap();
+        let now = chrono::Utc::now();
+        let expected =
+            [now, now - chrono::Duration::minutes(1)].map(|t| t.format("%H:%M").to_string());
+        assert!(expected.contains(&skipped_at));
+        defaults.assert();
+        mock2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_session_log_time_failure() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .expect(2)
+            .create_async()
+            .await;
+        let complete_mock = mock_command(&mut server, "7662880639.*item_close").await;
+        let note_mock = server
+            .mock("POST", "/sync/v9/sync")
+            .match_body(Matcher::Regex("note_add".to_string()))
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        server.get("/process?token=xxxx&filter=%23checklist").await;
+        let response = server
+            .get("/process?token=xxxx&filter=%23checklist&complete_task_id=7662880639&log_time=on")
+            .await;
+        response.assert_status_ok();
+        assert!(response.text().contains("You are all caught up!"));
+        defaults.assert();
+        mock2.assert();
+        complete_mock.assert();
+        note_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_settings() {
+        let mut server = mockito::Server::new_async().await;
+        let defaults = mock_process_defaults(&mut server).await;
+        let mock2 = mock_tasks(&mut server, &ResponseFromFile::Tasks.read().await)
+            .create_async()
+            .await;
+        let server = TestServer::new(routes(app_state(&server.url()))).unwrap();
+
+        let response = server
+            .get("/settings?token=xxxx&save=true&timezone=Asia%2FKolkata&default_filter=%23checklist&order=deadline&theme=dark")
+            .await;
+        let text = response.text();
+        assert!(text.contains("Your settings have been saved."));
+        assert!(text.contains("Asia/Kolkata"));
+
+        let response = server.get("/process?token=xxxx").await;
+        let text = response.text();
+        assert!(text.contains("Change water filter under sink"));
+        assert!(text.contains(r#"data-theme="dark""#));
+        defaults.assert();
         mock2.assert();
     }
 }
@@ -0,0 +1,214 @@
+//! Pomodoro mode alternates work intervals with enforced breaks.
+//! It lives in the account state so that it survives reloads and is shared across devices.
+use crate::account;
+use crate::error::Error;
+use crate::time::Timezone;
+use crate::{time, AppState};
+use chrono::{DateTime, Duration};
+use std::sync::Arc;
+
+const WORK_MINUTES: i64 = 25;
+const SHORT_BREAK_MINUTES: i64 = 5;
+const LONG_BREAK_MINUTES: i64 = 15;
+/// Every nth break is a long one
+const LONG_BREAK_EVERY: u32 = 4;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Pomodoro {
+    pub phase: Phase,
+    /// Work intervals finished since pomodoro mode was turned on
+    pub completed_intervals: u32,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Phase {
+    /// The work interval starts once a task is on screen
+    Waiting {
+        since: DateTime<Timezone>,
+    },
+    Work {
+        started_at: DateTime<Timezone>,
+    },
+    Break {
+        started_at: DateTime<Timezone>,
+        minutes: i64,
+    },
+}
+
+impl Pomodoro {
+    pub fn new(now: DateTime<Timezone>) -> Pomodoro {
+        Pomodoro {
+            phase: Phase::Waiting { since: now },
+            completed_intervals: 0,
+        }
+    }
+
+    /// Moves on to the phase we should be in at `now`.
+    /// Returns true when a work interval finished.
+    pub fn advance(&mut self, now: DateTime<Timezone>) -> bool {
+        let mut finished_work = false;
+
+        if let Phase::Work { started_at } = self.phase {
+            let ends_at = started_at + Duration::minutes(WORK_MINUTES);
+            if now >= ends_at {
+                self.completed_intervals += 1;
+                finished_work = true;
+                let minutes = if self.completed_intervals.is_multiple_of(LONG_BREAK_EVERY) {
+                    LONG_BREAK_MINUTES
+                } else {
+                    SHORT_BREAK_MINUTES
+                };
+                self.phase = Phase::Break {
+                    started_at: ends_at,
+                    minutes,
+                };
+            }
+        }
+
+        if let Phase::Break {
+            started_at,
+            minutes,
+        } = self.phase
+        {
+            if now >= started_at + Duration::minutes(minutes) {
+                self.phase = Phase::Waiting { since: now };
+            }
+        }
+
+        finished_work
+    }
+
+    /// Starts the work interval if it was waiting for a task to be shown
+    pub fn start_work(&mut self, now: DateTime<Timezone>) {
+        if let Phase::Waiting { .. } = self.phase {
+            self.phase = Phase::Work { started_at: now };
+        }
+    }
+
+    pub fn is_on_break(&self) -> bool {
+        matches!(self.phase, Phase::Break { .. })
+    }
+
+    pub fn ends_at(&self) -> DateTime<Timezone> {
+        match self.phase {
+            Phase::Waiting { since: started_at } | Phase::Work { started_at } => {
+                started_at + Duration::minutes(WORK_MINUTES)
+            }
+            Phase::Break {
+                started_at,
+                minutes,
+            } => started_at + Duration::minutes(minutes),
+        }
+    }
+
+    /// Time left in the current phase, formatted like 12:05
+    pub fn remaining(&self, now: DateTime<Timezone>) -> String {
+        let seconds = self
+            .ends_at()
+            .signed_duration_since(now)
+            .num_seconds()
+            .max(0);
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Turns pomodoro mode on or off when asked and advances the timer.
+/// A finished work interval is credited to the task that was on screen.
+pub async fn update(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    toggle: Option<&String>,
+    showing_task: bool,
+) -> Result<Option<Pomodoro>, Error> {
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+
+    match toggle.map(String::as_str) {
+        Some("on") if account_state.pomodoro.is_none() => {
+            account_state.pomodoro = Some(Pomodoro::new(now))
+        }
+        Some("off") => account_state.pomodoro = None,
+        _ => (),
+    }
+
+    if let Some(pomodoro) = account_state.pomodoro.as_mut() {
+        let finished_work = pomodoro.advance(now);
+        if let (true, Some(current)) = (finished_work, &account_state.current_task) {
+            *account_state
+                .pomodoro_counts
+                .entry(current.task_id.clone())
+                .or_default() += 1;
+        }
+        if showing_task {
+            pomodoro.start_work(now);
+        }
+    }
+    let pomodoro = account_state.pomodoro.clone();
+
+    account::save(app_state, token, account_state).await?;
+    Ok(pomodoro)
+}
+
+/// Work intervals spent on a task
+pub async fn count(app_state: &Arc<AppState>, token: &str, task_id: &str) -> Result<u32, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    Ok(account_state
+        .pomodoro_counts
+        .get(task_id)
+        .copied()
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_advance() {
+        let start = time::now(&Timezone::from(Tz::UTC)).unwrap();
+        let mut pomodoro = Pomodoro::new(start - Duration::minutes(5));
+        assert!(!pomodoro.advance(start));
+        pomodoro.start_work(start);
+        assert_eq!(pomodoro.phase, Phase::Work { started_at: start });
+
+        assert!(!pomodoro.advance(start + Duration::minutes(10)));
+        assert!(!pomodoro.is_on_break());
+
+        assert!(pomodoro.advance(start + Duration::minutes(26)));
+        assert!(pomodoro.is_on_break());
+        assert_eq!(pomodoro.ends_at(), start + Duration::minutes(30));
+        assert_eq!(pomodoro.remaining(start + Duration::minutes(26)), "4:00");
+
+        // The next interval waits for a task to be shown
+        assert!(!pomodoro.advance(start + Duration::minutes(31)));
+        assert!(!pomodoro.is_on_break());
+        let resumed = start + Duration::minutes(33);
+        pomodoro.start_work(resumed);
+        assert_eq!(
+            pomodoro.phase,
+            Phase::Work {
+                started_at: resumed
+            }
+        );
+
+        // Returning long after the interval ended skips the break that already passed
+        pomodoro.completed_intervals = 2;
+        assert!(pomodoro.advance(resumed + Duration::hours(3)));
+        assert_eq!(pomodoro.completed_intervals, 3);
+        assert!(!pomodoro.is_on_break());
+
+        pomodoro.phase = Phase::Work {
+            started_at: resumed,
+        };
+        pomodoro.advance(resumed + Duration::minutes(25));
+        assert_eq!(
+            pomodoro.phase,
+            Phase::Break {
+                started_at: resumed + Duration::minutes(25),
+                minutes: LONG_BREAK_MINUTES
+            }
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Per account preferences, edited on the settings page
+use crate::error::Error;
+use crate::tasks::Order;
+use crate::time;
+use crate::time::Timezone;
+use std::collections::HashMap;
+use std::str::FromStr;
+use strum::{Display, EnumString};
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Preferences {
+    /// Used instead of the timezone Todoist reports
+    pub timezone: Option<Timezone>,
+    /// Used when no filter is given
+    pub default_filter: Option<String>,
+    /// Used for filters that have not been given an order
+    pub order: Order,
+    pub theme: Theme,
+    pub skip: SkipMode,
+}
+
+#[derive(EnumString, Display, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum Theme {
+    /// Follow the operating system
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// What happens to a task once it is skipped
+#[derive(EnumString, Display, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum SkipMode {
+    /// Hidden until the queue is empty, the next session starts with it again
+    #[default]
+    Session,
+    /// Shown again after the rest of the queue
+    EndOfQueue,
+    /// Hidden until the queue is empty, then offered as a pile to go through again
+    Revisit,
+}
+
+impl Theme {
+    /// Value for Bulma's data-theme attribute
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Theme::System => None,
+            Theme::Light | Theme::Dark => Some(self.to_string()),
+        }
+    }
+}
+
+impl Preferences {
+    /// Builds preferences from the settings form, blank fields are unset
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Preferences, Error> {
+        let field = |name: &str| {
+            params
+                .get(name)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let invalid = |name: &str, value: &str| Error::BadRequest {
+            source: "preferences".to_string(),
+            message: format!("Invalid {name}: {value}"),
+        };
+
+        let timezone = match field("timezone") {
+            Some(timezone) => {
+                Some(time::timezone_from_str(timezone).map_err(|_| invalid("timezone", timezone))?)
+            }
+            None => None,
+        };
+        let order = match field("order") {
+            Some(order) => Order::from_str(order).map_err(|_| invalid("order", order))?,
+            None => Order::default(),
+        };
+        let theme = match field("theme") {
+            Some(theme) => Theme::from_str(theme).map_err(|_| invalid("theme", theme))?,
+            None => Theme::default(),
+        };
+        let skip = match field("skip") {
+            Some(skip) => SkipMode::from_str(skip).map_err(|_| invalid("skip", skip))?,
+            None => SkipMode::default(),
+        };
+
+        Ok(Preferences {
+            timezone,
+            default_filter: field("default_filter").map(String::from),
+            order,
+            theme,
+            skip,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_from_params() {
+        let params = HashMap::from([
+            ("timezone".to_string(), "Asia/Kolkata".to_string()),
+            ("default_filter".to_string(), " ".to_string()),
+            ("order".to_string(), "deadline".to_string()),
+            ("theme".to_string(), "dark".to_string()),
+            ("skip".to_string(), "end_of_queue".to_string()),
+        ]);
+        let preferences = Preferences::from_params(&params).unwrap();
+        assert_eq!(
+            preferences.timezone,
+            Some(Timezone::from(Tz::Asia__Kolkata))
+        );
+        assert_eq!(preferences.default_filter, None);
+        assert_eq!(preferences.order, Order::Deadline);
+        assert_eq!(preferences.theme.name(), Some("dark".to_string()));
+        assert_eq!(preferences.skip, SkipMode::EndOfQueue);
+
+        let params = HashMap::from([("timezone".to_string(), "Mars/Olympus".to_string())]);
+        assert!(Preferences::from_params(&params).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Projects and sections, used to show where a task lives
+use crate::error::Error;
+use crate::tasks::{self, Task};
+use crate::time::{self, Timezone};
+use crate::{account, request, AppState};
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const SYNC_URL: &str = "/sync/v9/sync";
+const CACHE_PROJECTS_MAX_AGE_MINUTES: i64 = 60;
+const DEFAULT_COLOR: &str = "#808080";
+
+/// The projects and sections resources from https://developer.todoist.com/sync/v9/#read-resources
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Projects {
+    #[serde(default)]
+    pub projects: Vec<Project>,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    /// A Todoist colour name such as berry_red
+    pub color: String,
+    #[serde(default)]
+    pub is_archived: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub id: String,
+    pub name: String,
+    pub project_id: String,
+    #[serde(default)]
+    pub is_archived: bool,
+}
+
+/// Where a task can be moved to, written as project:ID or section:ID in forms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    Project(String),
+    Section(String),
+}
+
+/// An option in the move picker
+pub struct Choice {
+    pub value: String,
+    pub name: String,
+}
+
+/// Project › Section › Parent task, each part is left out when unknown
+pub struct Breadcrumb {
+    pub project: Option<String>,
+    pub section: Option<String>,
+    /// Rendered from Markdown like the task content
+    pub parent: Option<String>,
+    pub color: String,
+}
+
+impl Projects {
+    pub fn project(&self, id: &str) -> Option<&Project> {
+        self.projects.iter().find(|p| p.id == id)
+    }
+
+    pub fn section(&self, id: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.id == id)
+    }
+
+    /// Falls back to the id for projects that have been deleted or shared with us since the cache was filled
+    pub fn project_name(&self, id: &str) -> String {
+        self.project(id)
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+impl Projects {
+    /// Each project followed by its sections, leaving out anything archived
+    pub fn choices(&self) -> Vec<Choice> {
+        let mut choices = Vec::new();
+        for project in self.projects.iter().filter(|p| !p.is_archived) {
+            choices.push(Choice {
+                value: Destination::Project(project.id.clone()).to_string(),
+                name: project.name.clone(),
+            });
+            for section in self
+                .sections
+                .iter()
+                .filter(|s| s.project_id == project.id && !s.is_archived)
+            {
+                choices.push(Choice {
+                    value: Destination::Section(section.id.clone()).to_string(),
+                    name: format!("{} › {}", project.name, section.name),
+                });
+            }
+        }
+        choices
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Project(id) => write!(f, "project:{id}"),
+            Destination::Section(id) => write!(f, "section:{id}"),
+        }
+    }
+}
+
+impl FromStr for Destination {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("project", id)) if !id.is_empty() => Ok(Destination::Project(id.to_string())),
+            Some(("section", id)) if !id.is_empty() => Ok(Destination::Section(id.to_string())),
+            _ => Err(Error::BadRequest {
+                source: String::from("Destination"),
+                message: format!("Unknown destination: {value}"),
+            }),
+        }
+    }
+}
+
+impl Project {
+    pub fn color_hex(&self) -> &'static str {
+        color_hex(&self.color)
+    }
+}
+
+impl Breadcrumb {
+    /// The project and section, the parent is rendered separately as it is HTML
+    pub fn parts(&self) -> Vec<&str> {
+        [&self.project, &self.section]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts().is_empty() && self.parent.is_none()
+    }
+}
+
+/// Fetches from cache or API, shared by every filter on the account
+pub async fn cached_get_projects(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    test_server_url: &Option<String>,
+) -> Result<Projects, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    if let (Some(projects), Some(updated_at)) =
+        (account_state.projects, account_state.projects_updated_at)
+    {
+        if time::age_in_minutes(updated_at, timezone)? < CACHE_PROJECTS_MAX_AGE_MINUTES {
+            return Ok(projects);
+        }
+    }
+
+    let projects = get_projects(token, test_server_url).await?;
+    let account_state = account::get_or_create(app_state, token).await?;
+    let account_state = account::AccountState {
+        projects: Some(projects.clone()),
+        projects_updated_at: Some(time::now(timezone)?),
+        ..account_state
+    };
+    account::save(app_state, token, account_state).await?;
+
+    Ok(projects)
+}
+
+pub async fn get_projects(token: &str, test_server: &Option<String>) -> Result<Projects, Error> {
+    let url = SYNC_URL.to_string();
+    let body = json!({"resource_types": ["projects", "sections"], "sync_token": "*"});
+    let json = request::post_todoist_sync(token, &url, body, test_server).await?;
+    let projects: Projects = serde_json::from_str(&json)?;
+    Ok(projects)
+}
+
+/// The parent is looked up in the queue first, and fetched when it is not part of the filter.
+/// A parent that cannot be fetched is left out rather than failing the page.
+pub async fn breadcrumb(
+    app_state: &Arc<AppState>,
+    task: &Task,
+    projects: &Projects,
+    queue: &[Task],
+    token: &str,
+    timezone: &Timezone,
+    test_server_url: &Option<String>,
+) -> Result<Breadcrumb, Error> {
+    let parent = match &task.parent_id {
+        Some(parent_id) => match queue.iter().find(|t| &t.id == parent_id) {
+            Some(parent) => Some(parent.content.clone()),
+            None => {
+                cached_get_parent_content(app_state, token, parent_id, timezone, test_server_url)
+                    .await?
+            }
+        },
+        None => None,
+    };
+
+    Ok(build_breadcrumb(
+        task,
+        projects,
+        parent.as_deref().map(inline_markdown),
+    ))
+}
+
+/// Fetches from cache or API, None when the parent could not be fetched
+async fn cached_get_parent_content(
+    app_state: &Arc<AppState>,
+    token: &str,
+    parent_id: &str,
+    timezone: &Timezone,
+    test_server_url: &Option<String>,
+) -> Result<Option<String>, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    if let Some((content, updated_at)) = account_state.parents.get(parent_id) {
+        if time::age_in_minutes(*updated_at, timezone)? < CACHE_PROJECTS_MAX_AGE_MINUTES {
+            return Ok(Some(content.clone()));
+        }
+    }
+
+    let content = match tasks::get_task(token, parent_id, test_server_url.clone()).await {
+        Ok(parent) => parent.content,
+        Err(error) => {
+            tracing::warn!("Could not fetch parent task {parent_id}: {error:?}");
+            return Ok(None);
+        }
+    };
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+    account_state.parents.retain(|_, (_, updated_at)| {
+        now.signed_duration_since(*updated_at).num_minutes() < CACHE_PROJECTS_MAX_AGE_MINUTES
+    });
+    account_state
+        .parents
+        .insert(parent_id.to_string(), (content.clone(), now));
+    account::save(app_state, token, account_state).await?;
+
+    Ok(Some(content))
+}
+
+/// Markdown rendered without the surrounding paragraph so that it fits in a line of text
+fn inline_markdown(content: &str) -> String {
+    let html = comrak::markdown_to_html(content, &comrak::Options::default());
+    let html = html.trim_end();
+    html.strip_prefix("<p>")
+        .and_then(|html| html.strip_suffix("</p>"))
+        .unwrap_or(html)
+        .to_string()
+}
+
+fn build_breadcrumb(task: &Task, projects: &Projects, parent: Option<String>) -> Breadcrumb {
+    let project = projects.project(&task.project_id);
+    let section = task
+        .section_id
+        .as_deref()
+        .and_then(|id| projects.section(id));
+
+    Breadcrumb {
+        project: project.map(|p| p.name.clone()),
+        section: section.map(|s| s.name.clone()),
+        parent,
+        color: project
+            .map(Project::color_hex)
+            .unwrap_or(DEFAULT_COLOR)
+            .to_string(),
+    }
+}
+
+/// https://developer.todoist.com/guides/#colors
+fn color_hex(name: &str) -> &'static str {
+    match name {
+        "berry_red" => "#b8256f",
+        "red" => "#db4035",
+        "orange" => "#ff9933",
+        "yellow" => "#fad000",
+        "olive_green" => "#afb83b",
+        "lime_green" => "#7ecc49",
+        "green" => "#299438",
+        "mint_green" => "#6accbc",
+        "teal" => "#158fad",
+        "sky_blue" => "#14aaf5",
+        "light_blue" => "#96c3eb",
+        "blue" => "#4073ff",
+        "grape" => "#884dff",
+        "violet" => "#af38eb",
+        "lavender" => "#eb96eb",
+        "magenta" => "#e05194",
+        "salmon" => "#ff8d85",
+        "charcoal" => "#808080",
+        "grey" => "#b8b8b8",
+        "taupe" => "#ccac93",
+        _ => DEFAULT_COLOR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::ResponseFromFile;
+
+    #[tokio::test]
+    async fn test_build_breadcrumb() {
+        let projects: Projects =
+            serde_json::from_str(&ResponseFromFile::Projects.read().await).unwrap();
+        let mut task = tasks::rest_json_to_tasks(ResponseFromFile::Tasks.read().await)
+            .unwrap()
+            .remove(0);
+
+        let breadcrumb = build_breadcrumb(&task, &projects, None);
+        assert_eq!(breadcrumb.parts(), vec!["Household"]);
+        assert_eq!(breadcrumb.color, "#299438");
+
+        task.section_id = Some(String::from("159732004"));
+        let breadcrumb = build_breadcrumb(&task, &projects, Some(String::from("Kitchen")));
+        assert_eq!(breadcrumb.parts(), vec!["Household", "Maintenance"]);
+        assert_eq!(breadcrumb.parent, Some(String::from("Kitchen")));
+
+        task.project_id = String::from("404");
+        let breadcrumb = build_breadcrumb(&task, &projects, None);
+        assert!(breadcrumb.project.is_none());
+        assert_eq!(breadcrumb.color, DEFAULT_COLOR);
+        assert_eq!(projects.project_name("404"), "404");
+    }
+
+    #[test]
+    fn test_inline_markdown() {
+        assert_eq!(
+            inline_markdown("Clean the **kitchen**"),
+            "Clean the <strong>kitchen</strong>"
+        );
+        assert_eq!(
+            inline_markdown("<b>Raw</b>"),
+            "<!-- raw HTML omitted -->Raw<!-- raw HTML omitted -->"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_choices() {
+        let projects: Projects =
+            serde_json::from_str(&ResponseFromFile::Projects.read().await).unwrap();
+        let choices = projects.choices();
+        let names = choices
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["Inbox", "Household", "Household › Maintenance"]);
+
+        let destination = Destination::from_str(&choices[2].value).unwrap();
+        assert_eq!(destination, Destination::Section(String::from("159732004")));
+        assert!(Destination::from_str("label:1").is_err());
+        assert!(Destination::from_str("project:").is_err());
+    }
+}
@@ -65,7 +65,13 @@ pub async fn get_random_unsplash(api_key: String) -> Result<String, Error> {
         .send()
         .await?;
 
-    handle_response(response, "GET", &url, json!({})).await
+    // A rejected key is our problem, not the user's Todoist token
+    handle_response(response, "GET", &url, json!({}))
+        .await
+        .map_err(|error| match error {
+            Error::InvalidToken { source, message } => Error::Internal { source, message },
+            error => error,
+        })
 }
 
 async fn handle_response(
@@ -78,8 +84,10 @@ async fn handle_response(
         println!("{method}: {url}");
         Ok(response.text().await?)
     } else {
+        let status = response.status();
         let json_string = response.text().await?;
-        Err(error::new(
+        Err(error::from_status(
+            status,
             "reqwest",
             &format!(
                 "
@@ -1,5 +1,8 @@
 //! Responses are for generating JSON and mocking API calls
 
+#[cfg(test)]
+use crate::tasks::{self, Task};
+
 /// File name is the same as the enum name
 /// So you can find the `Task` variant in tests/responses/Task.json
 #[allow(dead_code)]
@@ -7,15 +10,33 @@
 pub enum ResponseFromFile {
     Tasks,
     Sync,
+    Stats,
+    Projects,
+    Comments,
 }
 
 #[allow(dead_code)]
 impl ResponseFromFile {
     /// Loads JSON responses from file for testing
     pub async fn read(&self) -> String {
+        self.read_sync()
+    }
+
+    fn read_sync(&self) -> String {
         let path = format!("tests/{self}.json");
 
         std::fs::read_to_string(&path)
             .unwrap_or_else(|_| panic!("Could not find json file at {path}"))
     }
 }
+
+/// The first task from the Tasks fixture with a new id and whatever the test changes on top
+#[cfg(test)]
+pub fn fixture_task(id: &str, change: impl FnOnce(&mut Task)) -> Task {
+    let mut task = tasks::rest_json_to_tasks(ResponseFromFile::Tasks.read_sync())
+        .unwrap()
+        .remove(0);
+    task.id = id.to_string();
+    change(&mut task);
+    task
+}
@@ -0,0 +1,328 @@
+//! A focus session lasts from the first task shown until the queue is empty
+use crate::error::Error;
+use crate::preferences::SkipMode;
+use crate::tasks::Task;
+use crate::time::Timezone;
+use crate::{time, AppState, UserState};
+use chrono::DateTime;
+use std::fmt::Display;
+use std::sync::Arc;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Session {
+    pub started_at: DateTime<Timezone>,
+    /// Set once the queue is empty, the next task shown starts a new session
+    pub ended_at: Option<DateTime<Timezone>>,
+    /// Number of tasks in the queue when the session started
+    pub initial_count: usize,
+    pub actions: Vec<Action>,
+}
+
+/// Something the user did to a task, `task` is a snapshot from when it was shown
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub task: Task,
+    pub at: DateTime<Timezone>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ActionKind {
+    Completed,
+    Skipped,
+    /// Filed into another project or section
+    Moved,
+    Deleted,
+    /// Hidden for a while, it comes back later
+    Snoozed,
+    /// Given a due date far in the future
+    Postponed,
+}
+
+pub struct Progress {
+    pub done: usize,
+    pub skipped: usize,
+    pub remaining: usize,
+}
+
+pub struct Summary {
+    pub completed: Vec<Task>,
+    pub minutes_spent: u32,
+    pub skipped: usize,
+    /// The completed task that had been sitting in Todoist the longest
+    pub longest_waiting: Option<Task>,
+}
+
+impl Display for ActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            ActionKind::Completed => "Completed",
+            ActionKind::Skipped => "Skipped",
+            ActionKind::Moved => "Moved",
+            ActionKind::Deleted => "Deleted",
+            ActionKind::Snoozed => "Snoozed",
+            ActionKind::Postponed => "Postponed",
+        };
+        write!(f, "{text}")
+    }
+}
+
+impl Session {
+    pub fn new(started_at: DateTime<Timezone>, initial_count: usize) -> Session {
+        Session {
+            started_at,
+            ended_at: None,
+            initial_count,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, kind: ActionKind, task: Task, at: DateTime<Timezone>) {
+        self.actions.push(Action { kind, task, at });
+    }
+
+    /// Tasks that have been dealt with for good, by completing, moving, deleting or postponing them
+    pub fn done_task_ids(&self) -> Vec<&str> {
+        let mut ids = Vec::new();
+        for action in &self.actions {
+            let is_done = matches!(
+                action.kind,
+                ActionKind::Completed
+                    | ActionKind::Moved
+                    | ActionKind::Deleted
+                    | ActionKind::Postponed
+            );
+            if is_done && !ids.contains(&action.task.id.as_str()) {
+                ids.push(action.task.id.as_str());
+            }
+        }
+        ids
+    }
+
+    /// Tasks that were skipped and have not since been dealt with
+    pub fn skipped_task_ids(&self) -> Vec<&str> {
+        let done = self.done_task_ids();
+        let mut ids = Vec::new();
+        for action in &self.actions {
+            let id = action.task.id.as_str();
+            if action.kind == ActionKind::Skipped && !done.contains(&id) && !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// Progress against the current queue, which may have grown or shrunk since the session started.
+    /// Skipped tasks that are still queued, at the end with SkipMode::EndOfQueue, count as remaining.
+    pub fn progress(&self, tasks: &[Task], skip: SkipMode) -> Progress {
+        let skipped = self.skipped_task_ids();
+        let (skipped, remaining) = match skip {
+            SkipMode::EndOfQueue => (
+                skipped
+                    .iter()
+                    .filter(|id| !tasks.iter().any(|t| t.id == **id))
+                    .count(),
+                tasks.len(),
+            ),
+            SkipMode::Session | SkipMode::Revisit => (
+                skipped.len(),
+                tasks
+                    .iter()
+                    .filter(|t| !skipped.contains(&t.id.as_str()))
+                    .count(),
+            ),
+        };
+
+        Progress {
+            done: self.done_task_ids().len(),
+            skipped,
+            remaining,
+        }
+    }
+
+    pub fn summary(&self, timezone: &Timezone) -> Result<Summary, Error> {
+        let completed = self
+            .actions
+            .iter()
+            .filter(|a| a.kind == ActionKind::Completed)
+            .map(|a| a.task.clone())
+            .collect::<Vec<Task>>();
+        let ended_at = match self.ended_at {
+            Some(ended_at) => ended_at,
+            None => time::now(timezone)?,
+        };
+        let minutes_spent = ended_at
+            .signed_duration_since(self.started_at)
+            .num_minutes()
+            .max(0) as u32;
+        let longest_waiting = completed
+            .iter()
+            .filter(|t| t.created_at.is_some())
+            .min_by(|a, b| a.created_at.cmp(&b.created_at))
+            .cloned();
+
+        Ok(Summary {
+            skipped: self.skipped_task_ids().len(),
+            completed,
+            minutes_spent,
+            longest_waiting,
+        })
+    }
+
+    pub fn to_markdown(&self, timezone: &Timezone) -> Result<String, Error> {
+        let summary = self.summary(timezone)?;
+        let mut lines = vec![
+            format!(
+                "# SingleTask session {}",
+                self.started_at.format("%Y-%m-%d %H:%M")
+            ),
+            String::new(),
+            format!("- Time spent: {}", summary.time_spent()),
+            format!("- Completed: {}", summary.completed.len()),
+            format!("- Skipped: {}", summary.skipped),
+        ];
+        if let Some(task) = summary.longest_waiting {
+            lines.push(format!("- Longest waiting: {}", task.content));
+        }
+        lines.push(String::new());
+        lines.push(String::from("## Actions"));
+        lines.push(String::new());
+        for action in &self.actions {
+            lines.push(format!(
+                "- {} {}: {}",
+                action.at.format("%H:%M"),
+                action.kind,
+                action.task.content
+            ));
+        }
+
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+impl Summary {
+    pub fn time_spent(&self) -> String {
+        time::format_minutes(self.minutes_spent)
+    }
+}
+
+impl Progress {
+    pub fn total(&self) -> usize {
+        self.done + self.skipped + self.remaining
+    }
+
+    /// Tasks that have been dealt with, either done or skipped
+    pub fn processed(&self) -> usize {
+        self.done + self.skipped
+    }
+}
+
+/// Starts a session if there is none, records the action taken and returns the session.
+/// The session is ended once there are no tasks left.
+pub async fn record(
+    app_state: &Arc<AppState>,
+    key: &str,
+    timezone: &Timezone,
+    tasks: &[Task],
+    action: Option<(ActionKind, Task)>,
+) -> Result<Option<Session>, Error> {
+    let db = &app_state.db;
+    let user_state: Option<UserState> = db.begin(false).await.get(key.to_string())?;
+    let Some(user_state) = user_state else {
+        return Ok(None);
+    };
+    let now = time::now(timezone)?;
+
+    let session = match user_state.session.clone() {
+        Some(session) if session.ended_at.is_none() => Some(session),
+        _ if tasks.is_empty() && action.is_none() => user_state.session.clone(),
+        _ => {
+            let initial_count = tasks.len() + usize::from(action.is_some());
+            Some(Session::new(now, initial_count))
+        }
+    };
+    let session = session.map(|mut session| {
+        if let Some((kind, task)) = action {
+            session.record(kind, task, now);
+        }
+        if tasks.is_empty() && session.ended_at.is_none() {
+            session.ended_at = Some(now);
+        }
+        session
+    });
+
+    let mut tx = db.begin(true).await;
+    let user_state = UserState {
+        session: session.clone(),
+        ..user_state
+    };
+    tx.set(key.to_string(), user_state)?;
+    tx.commit()?;
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::fixture_task;
+    use chrono_tz::Tz;
+
+    fn task(id: &str, created_at: &str) -> Task {
+        fixture_task(id, |task| {
+            task.content = format!("Task {id}");
+            task.created_at = Some(created_at.to_string());
+        })
+    }
+
+    #[test]
+    fn test_progress() {
+        let now = time::now(&Timezone::from(Tz::UTC)).unwrap();
+        let mut session = Session::new(now, 5);
+        session.record(ActionKind::Completed, task("1", "2024-01-01"), now);
+        session.record(ActionKind::Skipped, task("2", "2024-01-01"), now);
+        session.record(ActionKind::Skipped, task("3", "2024-01-01"), now);
+        session.record(ActionKind::Completed, task("3", "2024-01-01"), now);
+        session.record(ActionKind::Skipped, task("6", "2024-01-01"), now);
+        session.record(ActionKind::Moved, task("6", "2024-01-01"), now);
+
+        // The skipped task came back after a cache refresh and a new task appeared
+        let tasks = [
+            task("2", "2024-01-01"),
+            task("4", "2024-01-01"),
+            task("5", "2024-01-01"),
+        ];
+        let progress = session.progress(&tasks, SkipMode::Session);
+        assert_eq!(progress.done, 3);
+        assert_eq!(progress.skipped, 1);
+        assert_eq!(progress.remaining, 2);
+        assert_eq!(progress.total(), 6);
+
+        // Skipped tasks wait at the end of the queue, so they are still to do
+        let progress = session.progress(&tasks, SkipMode::EndOfQueue);
+        assert_eq!(progress.done, 3);
+        assert_eq!(progress.skipped, 0);
+        assert_eq!(progress.remaining, 3);
+        assert_eq!(progress.total(), 6);
+    }
+
+    #[test]
+    fn test_summary() {
+        let now = time::now(&Timezone::from(Tz::UTC)).unwrap();
+        let mut session = Session::new(now - chrono::Duration::minutes(90), 3);
+        session.record(ActionKind::Completed, task("1", "2024-05-01"), now);
+        session.record(ActionKind::Skipped, task("2", "2020-01-01"), now);
+        session.record(ActionKind::Completed, task("3", "2023-01-01"), now);
+        session.ended_at = Some(now);
+
+        let summary = session.summary(&Timezone::from(Tz::UTC)).unwrap();
+        assert_eq!(summary.completed.len(), 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.minutes_spent, 90);
+        assert_eq!(summary.longest_waiting.unwrap().id, "3");
+
+        let markdown = session.to_markdown(&Timezone::from(Tz::UTC)).unwrap();
+        assert!(markdown.contains("- Time spent: 1h 30m"));
+        assert!(markdown.contains("Skipped: Task 2"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Skips are counted per task across sessions so that tasks that keep being put off stand out
+use crate::error::Error;
+use crate::time::{self, Timezone};
+use crate::{account, AppState};
+use chrono::{DateTime, Duration};
+use std::sync::Arc;
+
+/// Skips older than this are forgotten
+const WINDOW_DAYS: i64 = 14;
+/// Number of skips within the window before the task is flagged
+const ALERT_THRESHOLD: usize = 5;
+
+pub struct Alert {
+    pub count: usize,
+    /// Days since the first skip that is still counted
+    pub days: i64,
+}
+
+impl Alert {
+    pub fn text(&self) -> String {
+        let period = match self.days {
+            0 => String::from("today"),
+            1 => String::from("in 1 day"),
+            days if days < 7 => format!("in {days} days"),
+            7 => String::from("in 1 week"),
+            days => format!("in {} weeks", (days + 6) / 7),
+        };
+        format!("Skipped {} times {period}", self.count)
+    }
+}
+
+pub async fn record(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+) -> Result<(), Error> {
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+    let cutoff = now - Duration::days(WINDOW_DAYS);
+    for skipped_at in account_state.skips.values_mut() {
+        skipped_at.retain(|at| *at > cutoff);
+    }
+    account_state
+        .skips
+        .retain(|_, skipped_at| !skipped_at.is_empty());
+    account_state
+        .skips
+        .entry(task_id.to_string())
+        .or_default()
+        .push(now);
+    account::save(app_state, token, account_state).await
+}
+
+/// Starts the count again once something has been done about the task
+pub async fn forget(app_state: &Arc<AppState>, token: &str, task_id: &str) -> Result<(), Error> {
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    if account_state.skips.remove(task_id).is_some() {
+        account::save(app_state, token, account_state).await?;
+    }
+    Ok(())
+}
+
+/// Set when the task has been skipped too often recently
+pub async fn alert(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+) -> Result<Option<Alert>, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    let skipped_at = account_state
+        .skips
+        .get(task_id)
+        .cloned()
+        .unwrap_or_default();
+    Ok(to_alert(&skipped_at, time::now(timezone)?))
+}
+
+fn to_alert(skipped_at: &[DateTime<Timezone>], now: DateTime<Timezone>) -> Option<Alert> {
+    let cutoff = now - Duration::days(WINDOW_DAYS);
+    let recent: Vec<&DateTime<Timezone>> = skipped_at.iter().filter(|at| **at > cutoff).collect();
+    match recent.iter().min() {
+        Some(first) if recent.len() >= ALERT_THRESHOLD => Some(Alert {
+            count: recent.len(),
+            days: now.signed_duration_since(**first).num_days(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_to_alert() {
+        let now = time::now(&Timezone::from(Tz::America__Vancouver)).unwrap();
+        let days_ago = |days: &[i64]| {
+            days.iter()
+                .map(|d| now - Duration::days(*d))
+                .collect::<Vec<DateTime<Timezone>>>()
+        };
+
+        let alert = to_alert(&days_ago(&[13, 10, 6, 3, 2, 1, 0]), now).unwrap();
+        assert_eq!(alert.text(), "Skipped 7 times in 2 weeks");
+
+        let alert = to_alert(&days_ago(&[0, 0, 0, 0, 0]), now).unwrap();
+        assert_eq!(alert.text(), "Skipped 5 times today");
+
+        // Skips from before the window do not count
+        assert!(to_alert(&days_ago(&[20, 15, 3, 2, 1]), now).is_none());
+    }
+}
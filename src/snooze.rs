@@ -0,0 +1,94 @@
+//! Tasks hidden for a while without changing their due date in Todoist
+use crate::error::{self, Error};
+use crate::time::{self, Timezone};
+use crate::{account, AppState};
+use chrono::{DateTime, Duration, TimeZone};
+use std::sync::Arc;
+use strum::{Display, EnumString};
+
+#[derive(EnumString, Display, Debug, Clone, Copy, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum SnoozeFor {
+    FifteenMinutes,
+    OneHour,
+    /// Until midnight
+    Tomorrow,
+}
+
+impl SnoozeFor {
+    pub fn until(&self, timezone: &Timezone) -> Result<DateTime<Timezone>, Error> {
+        let now = time::now(timezone)?;
+        match self {
+            SnoozeFor::FifteenMinutes => Ok(now + Duration::minutes(15)),
+            SnoozeFor::OneHour => Ok(now + Duration::hours(1)),
+            SnoozeFor::Tomorrow => {
+                let midnight = (now.date_naive() + Duration::days(1))
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap_or_default();
+                timezone
+                    .from_local_datetime(&midnight)
+                    .earliest()
+                    .ok_or_else(|| error::new("snooze", "Midnight does not exist tomorrow"))
+            }
+        }
+    }
+}
+
+/// Hides the task in every filter until the time has passed
+pub async fn snooze(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+    snooze_for: SnoozeFor,
+) -> Result<(), Error> {
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+    account_state.snoozed.retain(|_, until| *until > now);
+    account_state
+        .snoozed
+        .insert(task_id.to_string(), snooze_for.until(timezone)?);
+    account::save(app_state, token, account_state).await
+}
+
+pub async fn snoozed_task_ids(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+) -> Result<Vec<String>, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+    Ok(account_state
+        .snoozed
+        .into_iter()
+        .filter(|(_, until)| *until > now)
+        .map(|(task_id, _)| task_id)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+    use chrono_tz::Tz;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_until() {
+        let timezone = Timezone::from(Tz::America__Vancouver);
+        let now = time::now(&timezone).unwrap();
+
+        let until = SnoozeFor::from_str("one_hour")
+            .unwrap()
+            .until(&timezone)
+            .unwrap();
+        assert_eq!(until.signed_duration_since(now).num_minutes(), 60);
+
+        let until = SnoozeFor::Tomorrow.until(&timezone).unwrap();
+        assert_eq!(
+            until.date_naive(),
+            time::today_date(&timezone).unwrap() + Duration::days(1)
+        );
+        assert_eq!((until.hour(), until.minute()), (0, 0));
+    }
+}
@@ -1,18 +1,31 @@
-use crate::error::Error;
+use crate::error::{self, Error};
+use crate::projects::Destination;
 use crate::request;
+use crate::time;
+use crate::time::Timezone;
 use chrono::DateTime;
+use chrono::Months;
 use chrono::NaiveDate;
-use chrono_tz::Tz;
+use chrono::NaiveDateTime;
 use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::fmt::Display;
+use strum::{Display as StrumDisplay, EnumString};
 use tokio::task::JoinHandle;
 use urlencoding::encode;
 use uuid::Uuid;
 
 const SYNC_URL: &str = "/sync/v9/sync";
 const REST_V2_TASKS_URL: &str = "/rest/v2/tasks/";
+const QUICK_ADD_URL: &str = "/sync/v9/quick/add";
+const TASK_APP_URL: &str = "https://app.todoist.com/app/task/";
+/// Verbs that start a conversation, the follow-up is then waiting for the reply
+const CONTACT_VERBS: [&str; 7] = ["email", "call", "message", "text", "ask", "ping", "contact"];
+pub const FOLLOW_UP_DUE: &str = "in 3 days";
+/// Far enough out to stop a task that keeps being skipped from coming up
+pub const POSTPONE_DUE: &str = "in 1 month";
 
 // Completes task inside another thread
 pub fn spawn_complete_task(
@@ -42,12 +55,258 @@ pub async fn complete_task(
     // Does not pass back a task
     Ok(String::from("✓"))
 }
+/// Adds a comment to a task, Todoist renders the content as Markdown
+pub async fn add_comment(
+    token: &str,
+    task_id: &str,
+    content: &str,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let body = json!({"commands": [{"type": "note_add", "uuid": uuid, "temp_id": uuid, "args": {"item_id": task_id, "content": content}}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+/// Sends the content, description, priority and labels of the task to Todoist
+pub async fn update_task(
+    token: &str,
+    task: &Task,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let body = json!({"commands": [{"type": "item_update", "uuid": uuid, "args": {
+        "id": task.id,
+        "content": task.content,
+        "description": task.description,
+        "priority": task.priority,
+        "labels": task.labels,
+    }}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+pub async fn move_task(
+    token: &str,
+    task_id: &str,
+    destination: &Destination,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let args = match destination {
+        Destination::Project(project_id) => json!({"id": task_id, "project_id": project_id}),
+        Destination::Section(section_id) => json!({"id": task_id, "section_id": section_id}),
+    };
+    let body = json!({"commands": [{"type": "item_move", "uuid": uuid, "args": args}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+pub async fn delete_task(
+    token: &str,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let body =
+        json!({"commands": [{"type": "item_delete", "uuid": uuid, "args": {"id": task_id}}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+/// Creates copies of the tasks in one request, comments are not copied.
+/// Subtasks must come after their parent, they are nested under the parent's copy.
+/// Returns the id of each new task keyed by the id of the task it copies.
+pub async fn recreate_tasks(
+    token: &str,
+    tasks: &[Task],
+    test_server_url: &Option<String>,
+) -> Result<HashMap<String, String>, Error> {
+    let mut temp_ids: HashMap<String, String> = HashMap::new();
+    let mut commands = Vec::new();
+    for task in tasks {
+        let uuid = Uuid::new_v4().to_string();
+        let parent_id = task
+            .parent_id
+            .as_ref()
+            .map(|parent_id| temp_ids.get(parent_id).unwrap_or(parent_id).clone());
+
+        let mut args = json!({
+            "content": task.content,
+            "description": task.description,
+            "priority": task.priority,
+            "labels": task.labels,
+            "project_id": task.project_id,
+            "section_id": task.section_id,
+            "parent_id": parent_id,
+        });
+        if let Some(due) = &task.due {
+            let date = due.datetime.as_ref().unwrap_or(&due.date);
+            args["due"] = json!({"date": date, "string": due.string, "timezone": due.timezone, "is_recurring": due.is_recurring});
+        }
+        if let Some(deadline) = &task.deadline {
+            args["deadline"] = json!({"date": deadline.date});
+        }
+        if let Some(duration) = &task.duration {
+            let unit = match duration.unit {
+                Unit::Minute => "minute",
+                Unit::Day => "day",
+            };
+            args["duration"] = json!({"amount": duration.amount, "unit": unit});
+        }
+        commands.push(json!({"type": "item_add", "uuid": uuid, "temp_id": uuid, "args": args}));
+        temp_ids.insert(task.id.clone(), uuid);
+    }
+    let body = json!({ "commands": commands });
+    let url = String::from(SYNC_URL);
+
+    let json = request::post_todoist_sync(token, &url, body, test_server_url).await?;
+    let response: serde_json::Value = serde_json::from_str(&json)?;
+    temp_ids
+        .into_iter()
+        .map(|(id, uuid)| {
+            response["temp_id_mapping"][&uuid]
+                .as_str()
+                .map(|new_id| (id, new_id.to_string()))
+                .ok_or_else(|| {
+                    error::new("recreate_tasks", "Todoist did not return the new task id")
+                })
+        })
+        .collect()
+}
+
+/// Adds a task to the inbox, Todoist parses dates, #project, @label and p1-p4 from the text
+pub async fn quick_add(
+    token: &str,
+    text: &str,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let body = json!({"text": text, "auto_reminder": true});
+    let url = String::from(QUICK_ADD_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+/// Adds a task in the same project and section, linking back to the original in the description
+pub async fn add_follow_up(
+    token: &str,
+    task: &Task,
+    content: &str,
+    due: &str,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let mut args = json!({
+        "content": content,
+        "description": follow_up_description(task),
+        "project_id": task.project_id,
+        "section_id": task.section_id,
+    });
+    if !due.is_empty() {
+        args["due"] = json!({"string": due});
+    }
+    let body =
+        json!({"commands": [{"type": "item_add", "uuid": uuid, "temp_id": uuid, "args": args}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+/// Pushes the due date out by POSTPONE_DUE
+pub async fn postpone_task(
+    token: &str,
+    task: &Task,
+    today: NaiveDate,
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let uuid = Uuid::new_v4().to_string();
+
+    let body = json!({"commands": [{"type": "item_update", "uuid": uuid, "args": {
+        "id": task.id,
+        "due": postpone_due(task, today),
+    }}]});
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
+/// A due string would replace the recurrence, so recurring tasks keep theirs
+/// and only have the date of the next occurrence moved
+fn postpone_due(task: &Task, today: NaiveDate) -> serde_json::Value {
+    match &task.due {
+        Some(due) if due.is_recurring => {
+            let date = today
+                .checked_add_months(Months::new(1))
+                .unwrap_or(today)
+                .format("%Y-%m-%d");
+            // Keep the time of day, and the Z of times in UTC
+            let time = due
+                .datetime
+                .as_deref()
+                .and_then(|datetime| datetime.get(10..))
+                .unwrap_or_default();
+            json!({"date": format!("{date}{time}"), "string": due.string, "is_recurring": true})
+        }
+        _ => json!({"string": POSTPONE_DUE}),
+    }
+}
+
+/// Adds a subtask for each line, in the same project and section as the parent
+pub async fn add_subtasks(
+    token: &str,
+    parent: &Task,
+    contents: &[&str],
+    test_server_url: &Option<String>,
+) -> Result<String, Error> {
+    let commands: Vec<serde_json::Value> = contents
+        .iter()
+        .map(|content| {
+            let uuid = Uuid::new_v4().to_string();
+            json!({"type": "item_add", "uuid": uuid, "temp_id": uuid, "args": {
+                "content": content,
+                "parent_id": parent.id,
+                "project_id": parent.project_id,
+                "section_id": parent.section_id,
+            }})
+        })
+        .collect();
+    let body = json!({ "commands": commands });
+    let url = String::from(SYNC_URL);
+
+    request::post_todoist_sync(token, &url, body, test_server_url).await?;
+
+    Ok(String::from("✓"))
+}
+
 pub async fn all_tasks(
     token: &str,
     filter: &str,
     test_server_url: &Option<String>,
 ) -> Result<Vec<Task>, Error> {
-    let filters = filter.split(",").collect::<Vec<&str>>();
+    let filters = split_filter(filter);
 
     let mut handles = Vec::new();
     for f in filters {
@@ -62,6 +321,11 @@ pub async fn all_tasks(
     Ok(tasks)
 }
 
+/// Splits a comma separated list of filters into the individual filters
+pub fn split_filter(filter: &str) -> Vec<&str> {
+    filter.split(",").collect::<Vec<&str>>()
+}
+
 pub async fn tasks_for_filter(
     token: &str,
     filter: &str,
@@ -69,16 +333,58 @@ pub async fn tasks_for_filter(
 ) -> Result<Vec<Task>, Error> {
     let encoded = encode(filter);
     let url = format!("{REST_V2_TASKS_URL}?filter={encoded}");
-    let json = request::get_todoist_rest(token, &url, test_server_url).await?;
+    let json = request::get_todoist_rest(token, &url, test_server_url)
+        .await
+        .map_err(|error| match error {
+            Error::BadRequest { source, message } => {
+                tracing::warn!("Filter \"{filter}\" was rejected: {message}");
+                Error::BadFilter {
+                    source,
+                    message: format!("Filter \"{filter}\" was rejected by Todoist"),
+                }
+            }
+            error => error,
+        })?;
     rest_json_to_tasks(json)
 }
 
+/// Every open task in the project, subtasks included
+pub async fn project_tasks(
+    token: &str,
+    project_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<Vec<Task>, Error> {
+    let url = format!("{REST_V2_TASKS_URL}?project_id={project_id}");
+    let json = request::get_todoist_rest(token, &url, test_server_url.clone()).await?;
+    rest_json_to_tasks(json)
+}
+
+pub async fn get_task(
+    token: &str,
+    task_id: &str,
+    test_server_url: Option<String>,
+) -> Result<Task, Error> {
+    let url = format!("{REST_V2_TASKS_URL}{task_id}");
+    let json = request::get_todoist_rest(token, &url, test_server_url).await?;
+    let task: Task = serde_json::from_str(&json)?;
+    Ok(task)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct Duration {
     pub amount: u32,
     pub unit: Unit,
 }
 
+impl Duration {
+    pub fn minutes(&self) -> u32 {
+        match self.unit {
+            Unit::Minute => self.amount,
+            Unit::Day => self.amount * 24 * 60,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum Unit {
     #[serde(rename(deserialize = "minute"))]
@@ -96,17 +402,43 @@ pub struct Task {
     pub labels: Vec<String>,
     pub parent_id: Option<String>,
     pub project_id: String,
+    pub section_id: Option<String>,
     pub due: Option<DateInfo>,
     /// Only on rest api return value
     pub is_completed: Option<bool>,
+    pub comment_count: Option<u32>,
     pub is_deleted: Option<bool>,
     /// only on sync api return value
     pub checked: Option<bool>,
     pub duration: Option<Duration>,
+    /// `added_at` on the sync api
+    #[serde(alias = "added_at")]
+    pub created_at: Option<String>,
+    pub deadline: Option<Deadline>,
+}
+
+/// Deadlines are always a date without a time
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Deadline {
+    pub date: String,
+}
+
+/// The order tasks are shown in
+#[derive(EnumString, StrumDisplay, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum Order {
+    /// As returned by Todoist
+    #[default]
+    Todoist,
+    /// Closest deadline first, tasks without a deadline last
+    Deadline,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct DateInfo {
+    /// Always YYYY-MM-DD on the rest api, the sync api puts the time in here too
     pub date: String,
+    /// Only on the rest api, set when the task has a due time
+    pub datetime: Option<String>,
     pub is_recurring: bool,
     pub string: String,
     pub timezone: Option<String>,
@@ -132,13 +464,191 @@ impl Display for Priority {
     }
 }
 
+impl Priority {
+    /// The number used by the API, 4 is the highest
+    pub fn value(&self) -> u8 {
+        self.clone() as u8
+    }
+}
+
+/// Applies the edits from the process page, fields that are not given are left alone
+pub fn apply_edit(task: &Task, params: &HashMap<String, String>) -> Result<Task, Error> {
+    let invalid = |field: &str, value: &str| Error::BadRequest {
+        source: String::from("apply_edit"),
+        message: format!("Invalid {field}: {value}"),
+    };
+
+    let content = match params.get("content").map(|c| c.trim()) {
+        Some("") => return Err(invalid("content", "")),
+        Some(content) => content.to_string(),
+        None => task.content.clone(),
+    };
+    let description = match params.get("description") {
+        Some(description) => description.trim().to_string(),
+        None => task.description.clone(),
+    };
+    let priority = match params.get("priority") {
+        Some(priority) => {
+            serde_json::from_str::<Priority>(priority).map_err(|_| invalid("priority", priority))?
+        }
+        None => task.priority.clone(),
+    };
+    let labels = match params.get("labels") {
+        Some(labels) => {
+            let mut parsed: Vec<String> = Vec::new();
+            for label in labels.split(',').map(|l| l.trim().trim_start_matches('@')) {
+                if !label.is_empty() && !parsed.iter().any(|l| l == label) {
+                    parsed.push(label.to_string());
+                }
+            }
+            parsed
+        }
+        None => task.labels.clone(),
+    };
+
+    Ok(Task {
+        content,
+        description,
+        priority,
+        labels,
+        ..task.clone()
+    })
+}
+
+/// Links back to the original task, its content is escaped so that it cannot break the link
+fn follow_up_description(task: &Task) -> String {
+    let mut text = String::new();
+    for c in task.content.chars() {
+        if matches!(c, '\\' | '[' | ']' | '(' | ')') {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    format!("Follow-up to [{text}]({})", task.app_url())
+}
+
+/// Suggests the content of a follow-up, "Email Bob about contract" becomes "Wait for Bob's reply"
+pub fn follow_up_content(content: &str) -> String {
+    let mut words = content.split_whitespace();
+    let verb = words.next().unwrap_or_default().to_lowercase();
+    let name = words
+        .next()
+        .unwrap_or_default()
+        .trim_matches(|c: char| !c.is_alphanumeric());
+
+    if CONTACT_VERBS.contains(&verb.as_str()) && name.starts_with(char::is_uppercase) {
+        if name.ends_with('s') {
+            format!("Wait for {name}' reply")
+        } else {
+            format!("Wait for {name}'s reply")
+        }
+    } else {
+        format!("Follow up on {content}")
+    }
+}
+
+/// Sum of the estimated durations of the tasks, ignoring those without one
+pub fn total_duration_minutes(tasks: &[Task]) -> u32 {
+    tasks
+        .iter()
+        .filter_map(|t| t.duration.as_ref().map(Duration::minutes))
+        .sum()
+}
+
+/// Sorting is stable so that ties keep the Todoist order
+pub fn sort(tasks: &mut [Task], order: Order) {
+    match order {
+        Order::Todoist => (),
+        Order::Deadline => tasks.sort_by_key(|t| (t.deadline_date().is_none(), t.deadline_date())),
+    }
+}
+
+/// Holds back tasks with a due time in the future.
+/// Returns the tasks that are ready and when the next held back task is due.
+pub fn hold_back_future(
+    tasks: Vec<Task>,
+    timezone: &Timezone,
+) -> Result<(Vec<Task>, Option<DateTime<Timezone>>), Error> {
+    let mut ready = Vec::new();
+    let mut next_up: Option<DateTime<Timezone>> = None;
+    for task in tasks {
+        match task.datetime_info(timezone)? {
+            DateTimeInfo::DateTime { datetime, .. }
+                if time::age_in_minutes(datetime, timezone)? < 0 =>
+            {
+                next_up = Some(next_up.map_or(datetime, |n| n.min(datetime)));
+            }
+            _ => ready.push(task),
+        }
+    }
+    Ok((ready, next_up))
+}
+
+/// Moves the given tasks behind the others, in the order of the ids
+pub fn move_to_end(tasks: Vec<Task>, task_ids: &[String]) -> Vec<Task> {
+    let (mut moved, mut tasks): (Vec<Task>, Vec<Task>) =
+        tasks.into_iter().partition(|t| task_ids.contains(&t.id));
+    moved.sort_by_key(|t| task_ids.iter().position(|id| id == &t.id));
+    tasks.extend(moved);
+    tasks
+}
+
+/// The ids of the task and everything nested below it that is also in the list
+pub fn with_descendant_ids(tasks: &[Task], task_id: &str) -> Vec<String> {
+    let mut ids = vec![task_id.to_string()];
+    let mut index = 0;
+    while index < ids.len() {
+        let id = ids[index].clone();
+        ids.extend(
+            tasks
+                .iter()
+                .filter(|t| t.parent_id.as_deref() == Some(id.as_str()))
+                .map(|t| t.id.clone()),
+        );
+        index += 1;
+    }
+    ids
+}
+
+/// Takes subtasks out of the queue when one of their ancestors is in it.
+/// Returns the queue and the subtasks keyed by the ancestor they are shown under.
+pub fn group_subtasks(tasks: Vec<Task>) -> (Vec<Task>, HashMap<String, Vec<Task>>) {
+    let parents: HashMap<String, Option<String>> = tasks
+        .iter()
+        .map(|t| (t.id.clone(), t.parent_id.clone()))
+        .collect();
+    let mut queue = Vec::new();
+    let mut subtasks: HashMap<String, Vec<Task>> = HashMap::new();
+    for task in tasks {
+        let mut ancestor = None;
+        let mut parent_id = task.parent_id.clone();
+        while let Some(id) = parent_id.filter(|id| parents.contains_key(id)) {
+            parent_id = parents[&id].clone();
+            ancestor = Some(id);
+        }
+        match ancestor {
+            Some(ancestor) => subtasks.entry(ancestor).or_default().push(task),
+            None => queue.push(task),
+        }
+    }
+    (queue, subtasks)
+}
+
+/// Holds back parents until the subtasks in the queue have been dealt with
+pub fn children_first(tasks: Vec<Task>) -> Vec<Task> {
+    let parent_ids: Vec<String> = tasks.iter().filter_map(|t| t.parent_id.clone()).collect();
+    tasks
+        .into_iter()
+        .filter(|t| !parent_ids.contains(&t.id))
+        .collect()
+}
+
 pub fn rest_json_to_tasks(json: String) -> Result<Vec<Task>, Error> {
     let tasks: Vec<Task> = serde_json::from_str(&json)?;
     Ok(tasks)
 }
 
-#[allow(dead_code)]
-enum DateTimeInfo {
+pub enum DateTimeInfo {
     NoDateTime,
     Date {
         date: NaiveDate,
@@ -146,8 +656,338 @@ enum DateTimeInfo {
         string: String,
     },
     DateTime {
-        datetime: DateTime<Tz>,
+        datetime: DateTime<Timezone>,
         is_recurring: bool,
         string: String,
     },
 }
+
+/// What the process page shows for a due date
+pub struct Due {
+    pub text: String,
+    pub is_overdue: bool,
+    /// Such as "every 3 months"
+    pub recurrence: Option<String>,
+}
+
+impl Task {
+    pub fn app_url(&self) -> String {
+        format!("{TASK_APP_URL}{}", self.id)
+    }
+
+    /// Floating datetimes are in the user's timezone, those ending in Z are in UTC
+    pub fn datetime_info(&self, timezone: &Timezone) -> Result<DateTimeInfo, Error> {
+        let Some(DateInfo {
+            date,
+            datetime,
+            is_recurring,
+            string,
+            ..
+        }) = self.due.clone()
+        else {
+            return Ok(DateTimeInfo::NoDateTime);
+        };
+        let date = datetime.unwrap_or(date);
+
+        if time::is_date(&date) {
+            Ok(DateTimeInfo::Date {
+                date: NaiveDate::parse_from_str(&date, "%Y-%m-%d")?,
+                is_recurring,
+                string,
+            })
+        } else if time::is_datetime(&date) && date.ends_with('Z') {
+            Ok(DateTimeInfo::DateTime {
+                datetime: DateTime::parse_from_rfc3339(&date)?.with_timezone(timezone),
+                is_recurring,
+                string,
+            })
+        } else if time::is_datetime(&date) {
+            let naive = NaiveDateTime::parse_from_str(&date, "%Y-%m-%dT%H:%M:%S%.f")?;
+            Ok(DateTimeInfo::DateTime {
+                datetime: time::from_local(naive, timezone),
+                is_recurring,
+                string,
+            })
+        } else {
+            Err(error::new(
+                "datetime_info",
+                &format!("Could not parse due date {date}"),
+            ))
+        }
+    }
+
+    pub fn deadline_date(&self) -> Option<NaiveDate> {
+        self.deadline
+            .as_ref()
+            .and_then(|d| NaiveDate::parse_from_str(&d.date, "%Y-%m-%d").ok())
+    }
+
+    pub fn deadline_display(&self, timezone: &Timezone) -> Result<Option<Due>, Error> {
+        let due = match self.deadline_date() {
+            Some(date) => Some(Due {
+                text: time::format_countdown(&date, timezone)?,
+                is_overdue: time::days_from_today(date, timezone)? < 0,
+                recurrence: None,
+            }),
+            None => None,
+        };
+        Ok(due)
+    }
+
+    pub fn due_display(&self, timezone: &Timezone) -> Result<Option<Due>, Error> {
+        let due = match self.datetime_info(timezone)? {
+            DateTimeInfo::NoDateTime => None,
+            DateTimeInfo::Date {
+                date,
+                is_recurring,
+                string,
+            } => Some(Due {
+                text: time::format_date(&date, timezone)?,
+                is_overdue: time::days_from_today(date, timezone)? < 0,
+                recurrence: is_recurring.then_some(string),
+            }),
+            DateTimeInfo::DateTime {
+                datetime,
+                is_recurring,
+                string,
+            } => Some(Due {
+                text: time::format_datetime(&datetime, timezone)?,
+                is_overdue: time::age_in_minutes(datetime, timezone)? > 0,
+                recurrence: is_recurring.then_some(string),
+            }),
+        };
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::fixture_task;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_datetime_info() {
+        let task = fixture_task("1", |_| {});
+        let tz = Timezone::from(Tz::America__Vancouver);
+
+        assert!(matches!(
+            task.datetime_info(&tz).unwrap(),
+            DateTimeInfo::Date {
+                is_recurring: true,
+                ..
+            }
+        ));
+
+        // The rest api keeps the date on its own and adds the time when there is one
+        let with_datetime = |datetime: &str| {
+            fixture_task("1", |task| {
+                if let Some(due) = task.due.as_mut() {
+                    due.date = datetime[..10].to_string();
+                    due.datetime = Some(datetime.to_string());
+                }
+            })
+        };
+
+        let DateTimeInfo::DateTime { datetime, .. } = with_datetime("2024-05-14T17:00:00.000000")
+            .datetime_info(&tz)
+            .unwrap()
+        else {
+            panic!("Expected a datetime");
+        };
+        assert_eq!(datetime.to_rfc3339(), "2024-05-14T17:00:00-07:00");
+
+        let DateTimeInfo::DateTime { datetime, .. } = with_datetime("2024-05-14T17:00:00.000000Z")
+            .datetime_info(&tz)
+            .unwrap()
+        else {
+            panic!("Expected a datetime");
+        };
+        assert_eq!(datetime.to_rfc3339(), "2024-05-14T10:00:00-07:00");
+
+        // Skipped when the clocks went forward
+        let DateTimeInfo::DateTime { datetime, .. } = with_datetime("2024-03-10T02:30:00.000000")
+            .datetime_info(&tz)
+            .unwrap()
+        else {
+            panic!("Expected a datetime");
+        };
+        assert_eq!(datetime.to_rfc3339(), "2024-03-10T03:30:00-07:00");
+    }
+
+    #[test]
+    fn test_hold_back_future() {
+        let tz = Timezone::from(Tz::America__Vancouver);
+        let now = time::now(&tz).unwrap();
+        // The rest api gives the time in due.datetime, floating or in UTC
+        let with_due = |id: &str, datetime: Option<String>| {
+            fixture_task(id, |task| {
+                if let Some(due) = task.due.as_mut() {
+                    due.date = now.format("%Y-%m-%d").to_string();
+                    due.datetime = datetime;
+                }
+            })
+        };
+        let floating = |datetime: DateTime<Timezone>| {
+            Some(datetime.format("%Y-%m-%dT%H:%M:%S%.6f").to_string())
+        };
+        let utc = |datetime: DateTime<Timezone>| {
+            Some(
+                datetime
+                    .with_timezone(&chrono::Utc)
+                    .format("%Y-%m-%dT%H:%M:%S%.6fZ")
+                    .to_string(),
+            )
+        };
+        let later = now + chrono::Duration::hours(2);
+        let tasks = vec![
+            with_due("1", None),
+            with_due("2", floating(now - chrono::Duration::hours(1))),
+            with_due("3", utc(now + chrono::Duration::hours(5))),
+            with_due("4", floating(later)),
+            with_due("5", utc(now - chrono::Duration::minutes(30))),
+        ];
+
+        let (ready, next_up) = hold_back_future(tasks, &tz).unwrap();
+        let ids = ready.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["1", "2", "5"]);
+        assert_eq!(
+            next_up.unwrap().format("%H:%M").to_string(),
+            later.format("%H:%M").to_string()
+        );
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let with_parent = |id: &str, parent_id: Option<&str>| {
+            fixture_task(id, |task| task.parent_id = parent_id.map(String::from))
+        };
+        // 4 is nested below 2, the parent of 5 is not in the queue
+        let tasks = vec![
+            with_parent("1", None),
+            with_parent("2", Some("1")),
+            with_parent("3", Some("1")),
+            with_parent("4", Some("2")),
+            with_parent("5", Some("99")),
+        ];
+        let ids = |tasks: &[Task]| tasks.iter().map(|t| t.id.clone()).collect::<Vec<String>>();
+
+        let (queue, subtasks) = group_subtasks(tasks.clone());
+        assert_eq!(ids(&queue), vec!["1", "5"]);
+        assert_eq!(ids(&subtasks["1"]), vec!["2", "3", "4"]);
+        assert_eq!(subtasks.len(), 1);
+
+        assert_eq!(ids(&children_first(tasks.clone())), vec!["3", "4", "5"]);
+        let skipped = vec!["2".to_string(), "1".to_string()];
+        assert_eq!(
+            ids(&move_to_end(tasks.clone(), &skipped)),
+            vec!["3", "4", "5", "2", "1"]
+        );
+        assert_eq!(with_descendant_ids(&tasks, "1"), vec!["1", "2", "3", "4"]);
+        assert_eq!(with_descendant_ids(&tasks, "3"), vec!["3"]);
+    }
+
+    #[test]
+    fn test_sort_by_deadline() {
+        let with_deadline = |id: &str, date: Option<&str>| {
+            fixture_task(id, |task| {
+                task.deadline = date.map(|date| Deadline {
+                    date: date.to_string(),
+                })
+            })
+        };
+        let mut tasks = vec![
+            with_deadline("1", None),
+            with_deadline("2", Some("2025-06-01")),
+            with_deadline("3", None),
+            with_deadline("4", Some("2025-05-20")),
+        ];
+
+        sort(&mut tasks, Order::Todoist);
+        assert_eq!(tasks[0].id, "1");
+
+        sort(&mut tasks, Order::Deadline);
+        let ids = tasks.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["4", "2", "1", "3"]);
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let task = fixture_task("1", |_| {});
+        let params = HashMap::from([
+            ("content".to_string(), " Change the filters ".to_string()),
+            ("priority".to_string(), "4".to_string()),
+            (
+                "labels".to_string(),
+                "physical, @errand,,physical".to_string(),
+            ),
+        ]);
+
+        let edited = apply_edit(&task, &params).unwrap();
+        assert_eq!(edited.content, "Change the filters");
+        assert_eq!(edited.description, task.description);
+        assert_eq!(edited.priority, Priority::High);
+        assert_eq!(edited.labels, vec!["physical", "errand"]);
+
+        let params = HashMap::from([("priority".to_string(), "7".to_string())]);
+        assert!(apply_edit(&task, &params).is_err());
+        let params = HashMap::from([("content".to_string(), "  ".to_string())]);
+        assert!(apply_edit(&task, &params).is_err());
+    }
+
+    #[test]
+    fn test_follow_up_content() {
+        assert_eq!(
+            follow_up_content("Email Bob about contract"),
+            "Wait for Bob's reply"
+        );
+        assert_eq!(
+            follow_up_content("call Chris, re: invoice"),
+            "Wait for Chris' reply"
+        );
+        assert_eq!(
+            follow_up_content("Email the landlord"),
+            "Follow up on Email the landlord"
+        );
+        assert_eq!(
+            follow_up_content("Change water filter"),
+            "Follow up on Change water filter"
+        );
+    }
+
+    #[test]
+    fn test_follow_up_description() {
+        let task = fixture_task("7662880639", |task| {
+            task.content = String::from("Ask [Bob] about (the) filter")
+        });
+        assert_eq!(
+            follow_up_description(&task),
+            r"Follow-up to [Ask \[Bob\] about \(the\) filter](https://app.todoist.com/app/task/7662880639)"
+        );
+    }
+
+    #[test]
+    fn test_postpone_due() {
+        let recurring = fixture_task("1", |_| {});
+        let today = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+
+        // The recurrence is kept and the next occurrence moved
+        assert_eq!(
+            postpone_due(&recurring, today),
+            json!({"date": "2024-02-29", "string": "every 3 months", "is_recurring": true})
+        );
+
+        let timed = fixture_task("1", |task| {
+            if let Some(due) = task.due.as_mut() {
+                due.datetime = Some(String::from("2024-01-10T09:00:00.000000Z"));
+            }
+        });
+        assert_eq!(
+            postpone_due(&timed, today)["date"],
+            "2024-02-29T09:00:00.000000Z"
+        );
+
+        let once = fixture_task("1", |task| task.due = None);
+        assert_eq!(postpone_due(&once, today), json!({"string": "in 1 month"}));
+    }
+}
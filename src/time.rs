@@ -1,130 +1,376 @@
 use crate::error::{self, Error};
-use chrono::offset::Utc;
-use chrono::DateTime;
-use chrono_tz::Tz;
-// use regex::Regex;
+use chrono::offset::{MappedLocalTime, Utc};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::{Tz, TzOffset};
+use regex::Regex;
+use std::fmt::Display;
 
-pub fn now(timezone: &Tz) -> Result<DateTime<Tz>, Error> {
-    Ok(Utc::now().with_timezone(timezone))
+/// A user's timezone, Todoist reports either an IANA name or an offset like GMT +5:30
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timezone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimezoneOffset {
+    Named(TzOffset),
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// The IANA name, or the offset in the same form Todoist uses
+    pub fn name(&self) -> String {
+        match self {
+            Timezone::Named(tz) => tz.name().to_string(),
+            Timezone::Fixed(offset) => {
+                let seconds = offset.local_minus_utc();
+                let sign = if seconds < 0 { '-' } else { '+' };
+                let minutes = seconds.abs() / 60;
+                format!("GMT {sign}{}:{:02}", minutes / 60, minutes % 60)
+            }
+        }
+    }
+}
+
+impl From<Tz> for Timezone {
+    fn from(value: Tz) -> Self {
+        Timezone::Named(value)
+    }
+}
+
+impl Offset for TimezoneOffset {
+    fn fix(&self) -> FixedOffset {
+        match self {
+            TimezoneOffset::Named(offset) => offset.fix(),
+            TimezoneOffset::Fixed(offset) => *offset,
+        }
+    }
+}
+
+impl Display for TimezoneOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimezoneOffset::Named(offset) => write!(f, "{offset}"),
+            TimezoneOffset::Fixed(offset) => write!(f, "{offset}"),
+        }
+    }
 }
 
-// Return today's date in format 2021-09-16
-// pub fn today_string(timezone: &String) -> Result<String, Error> {
-//     Ok(now(timezone)?.format("%Y-%m-%d").to_string())
-// }
+impl TimeZone for Timezone {
+    type Offset = TimezoneOffset;
+
+    fn from_offset(offset: &TimezoneOffset) -> Self {
+        match offset {
+            TimezoneOffset::Named(offset) => Timezone::Named(Tz::from_offset(offset)),
+            TimezoneOffset::Fixed(offset) => Timezone::Fixed(*offset),
+        }
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<TimezoneOffset> {
+        match self {
+            Timezone::Named(tz) => tz.offset_from_local_date(local).map(TimezoneOffset::Named),
+            Timezone::Fixed(offset) => offset
+                .offset_from_local_date(local)
+                .map(TimezoneOffset::Fixed),
+        }
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<TimezoneOffset> {
+        match self {
+            Timezone::Named(tz) => tz
+                .offset_from_local_datetime(local)
+                .map(TimezoneOffset::Named),
+            Timezone::Fixed(offset) => offset
+                .offset_from_local_datetime(local)
+                .map(TimezoneOffset::Fixed),
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> TimezoneOffset {
+        match self {
+            Timezone::Named(tz) => TimezoneOffset::Named(tz.offset_from_utc_date(utc)),
+            Timezone::Fixed(offset) => TimezoneOffset::Fixed(offset.offset_from_utc_date(utc)),
+        }
+    }
 
-// Return today's date in Utc
-// pub fn today_date(timezone: &String) -> Result<NaiveDate, Error> {
-//     Ok(now(timezone)?.date_naive())
-// }
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> TimezoneOffset {
+        match self {
+            Timezone::Named(tz) => TimezoneOffset::Named(tz.offset_from_utc_datetime(utc)),
+            Timezone::Fixed(offset) => TimezoneOffset::Fixed(offset.offset_from_utc_datetime(utc)),
+        }
+    }
+}
 
-// pub fn datetime_is_today(datetime: DateTime<Tz>, timezone: String) -> Result<bool, Error> {
-//     date_is_today(datetime.date_naive(), timezone)
-// }
+pub fn now(timezone: &Timezone) -> Result<DateTime<Timezone>, Error> {
+    Ok(Utc::now().with_timezone(timezone))
+}
 
-// pub fn date_is_today(date: NaiveDate, timezone: String) -> Result<bool, Error> {
-//     let date_string = date.format("%Y-%m-%d").to_string();
-//     let today_string = today_string(&timezone)?;
-//     Ok(date_string == today_string)
-// }
+/// A wall clock time in the timezone. Times skipped by a daylight saving change are read
+/// with the offset from just before it, so 02:30 becomes 03:30 when clocks jump from 02:00.
+pub fn from_local(naive: NaiveDateTime, timezone: &Timezone) -> DateTime<Timezone> {
+    match timezone.from_local_datetime(&naive) {
+        MappedLocalTime::Single(datetime) | MappedLocalTime::Ambiguous(datetime, _) => datetime,
+        MappedLocalTime::None => {
+            let before = naive - Duration::hours(3);
+            let offset = timezone.offset_from_utc_datetime(&before).fix();
+            timezone.from_utc_datetime(&(naive - offset))
+        }
+    }
+}
 
-// pub fn date_is_today(date: NaiveDate, timezone: String) -> Result<bool, Error> {
-//     let date_string = date.format("%Y-%m-%d").to_string();
-//     let today_string = today_string(&timezone)?;
-//     Ok(date_string == today_string)
-// }
+/// Return today's date in the timezone
+pub fn today_date(timezone: &Timezone) -> Result<NaiveDate, Error> {
+    Ok(now(timezone)?.date_naive())
+}
+
+/// Days from today until the date, negative when it is in the past
+pub fn days_from_today(date: NaiveDate, timezone: &Timezone) -> Result<i64, Error> {
+    Ok(date.signed_duration_since(today_date(timezone)?).num_days())
+}
 
 /// How far in the past a datetime is, in minutes.
 /// Postive in past, negative in future.
-pub fn age_in_minutes(datetime: DateTime<Tz>, timezone: &Tz) -> Result<i64, Error> {
+pub fn age_in_minutes(datetime: DateTime<Timezone>, timezone: &Timezone) -> Result<i64, Error> {
     let num_minutes = -datetime.signed_duration_since(now(timezone)?).num_minutes();
     Ok(num_minutes)
 }
 
-// pub fn format_date(date: &NaiveDate, timezone: String) -> Result<String, Error> {
-//     if date_is_today(*date, timezone)? {
-//         Ok(String::from("Today"))
-//     } else {
-//         Ok(date.format("%Y-%m-%d").to_string())
-//     }
-// }
-
-// pub fn format_datetime(datetime: &DateTime<Tz>, timezone: String) -> Result<String, Error> {
-//     let tz = timezone_from_str(&timezone)?;
-//     if datetime_is_today(*datetime, timezone)? {
-//         Ok(datetime.with_timezone(&tz).format("%H:%M").to_string())
-//     } else {
-//         Ok(datetime.with_timezone(&tz).to_string())
-//     }
-// }
-
-pub fn timezone_from_str(timezone: &str) -> Result<Tz, Error> {
+/// Relative description such as Today, Tomorrow, Friday or 3 days overdue
+pub fn format_date(date: &NaiveDate, timezone: &Timezone) -> Result<String, Error> {
+    let days = days_from_today(*date, timezone)?;
+    let text = match days {
+        0 => String::from("Today"),
+        1 => String::from("Tomorrow"),
+        -1 => String::from("1 day overdue"),
+        days if days < 0 => format!("{} days overdue", -days),
+        days if days < 7 => date.format("%A").to_string(),
+        _ => date.format("%Y-%m-%d").to_string(),
+    };
+    Ok(text)
+}
+
+/// Countdown to a date such as 5 days left or 2 days overdue
+pub fn format_countdown(date: &NaiveDate, timezone: &Timezone) -> Result<String, Error> {
+    let days = days_from_today(*date, timezone)?;
+    let text = match days {
+        0 => String::from("Today"),
+        1 => String::from("1 day left"),
+        -1 => String::from("1 day overdue"),
+        days if days < 0 => format!("{} days overdue", -days),
+        days => format!("{days} days left"),
+    };
+    Ok(text)
+}
+
+/// Like `format_date` with the time added for today and upcoming days
+pub fn format_datetime(
+    datetime: &DateTime<Timezone>,
+    timezone: &Timezone,
+) -> Result<String, Error> {
+    let datetime = datetime.with_timezone(timezone);
+    let date = format_date(&datetime.date_naive(), timezone)?;
+    if days_from_today(datetime.date_naive(), timezone)? < 0 {
+        Ok(date)
+    } else {
+        Ok(format!("{date} {}", datetime.format("%H:%M")))
+    }
+}
+
+/// Formats a number of minutes like 1d 2h 30m
+pub fn format_minutes(minutes: u32) -> String {
+    let days = minutes / (24 * 60);
+    let hours = minutes % (24 * 60) / 60;
+    let minutes = minutes % 60;
+
+    let parts = [(days, "d"), (hours, "h"), (minutes, "m")]
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{amount}{unit}"))
+        .collect::<Vec<String>>();
+
+    if parts.is_empty() {
+        String::from("0m")
+    } else {
+        parts.join(" ")
+    }
+}
+
+pub fn timezone_from_str(timezone: &str) -> Result<Timezone, Error> {
     match timezone.parse::<Tz>() {
-        Ok(tz) => Ok(tz),
+        Ok(tz) => Ok(Timezone::Named(tz)),
         Err(_) => parse_gmt_to_timezone(timezone),
     }
 }
 
-/// For when we get offsets like GMT -7:00
-fn parse_gmt_to_timezone(gmt: &str) -> Result<Tz, Error> {
-    let split: Vec<&str> = gmt.split_whitespace().collect();
-    let offset = split
-        .get(1)
-        .ok_or_else(|| error::new("parse_timezone", "Could not get offset"))?;
-    let offset = offset.replace(":00", "");
-    let offset = offset.replace(':', "");
-    let offset_num = offset.parse::<i32>()?;
-
-    let tz_string = format!(
-        "Etc/GMT{}",
-        if offset_num < 0 {
-            "+".to_string()
-        } else {
-            "-".to_string()
-        } + &offset_num.abs().to_string()
-    );
-    tz_string.parse().map_err(Error::from)
-}
-
-// Checks if string is a date in format YYYY-MM-DD
-// pub fn is_date(string: &str) -> bool {
-//     let re = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
-//     re.is_match(string)
-// }
-
-// Checks if string is a datetime in format YYYY-MM-DD HH:MM
-// pub fn is_datetime(string: &str) -> bool {
-//     let re = Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}$").unwrap();
-//     re.is_match(string)
-// }
+/// For when we get offsets like GMT -7:00 or GMT +5:45
+fn parse_gmt_to_timezone(gmt: &str) -> Result<Timezone, Error> {
+    let re = Regex::new(r"^GMT\s*([+-])(\d{1,2}):?([0-5]\d)?$").unwrap();
+    let captures = re.captures(gmt.trim()).ok_or_else(|| {
+        error::new(
+            "parse_timezone",
+            &format!("Could not get offset from {gmt}"),
+        )
+    })?;
+    let hours = captures[2].parse::<i32>()?;
+    let minutes = match captures.get(3) {
+        Some(minutes) => minutes.as_str().parse::<i32>()?,
+        None => 0,
+    };
+    let seconds = (hours * 60 + minutes) * 60;
+    let seconds = if &captures[1] == "-" {
+        -seconds
+    } else {
+        seconds
+    };
+
+    FixedOffset::east_opt(seconds)
+        .map(Timezone::Fixed)
+        .ok_or_else(|| error::new("parse_timezone", &format!("Offset out of range in {gmt}")))
+}
+
+/// Checks if string is a date in format YYYY-MM-DD
+pub fn is_date(string: &str) -> bool {
+    let re = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+    re.is_match(string)
+}
+
+/// Checks if string is a datetime in format YYYY-MM-DDTHH:MM:SS, with optional fractional
+/// seconds and a Z when it is in UTC
+pub fn is_datetime(string: &str) -> bool {
+    let re = Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?Z?$").unwrap();
+    re.is_match(string)
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_is_date() {
+        assert!(is_date("2022-10-05"));
+        assert!(!is_date("22-10-05"));
+        assert!(!is_date("2022-10-05T14:02:00"));
+        assert!(!is_date("today"));
+    }
+
+    #[test]
+    fn test_is_datetime() {
+        assert!(!is_datetime("2022-10-05"));
+        assert!(!is_datetime("22-10-05"));
+        assert!(is_datetime("2022-10-05T14:02:00"));
+        assert!(is_datetime("2022-10-05T14:02:00Z"));
+        assert!(is_datetime("2022-10-05T14:02:00.000000Z"));
+        assert!(is_datetime("2022-10-05T14:02:00.000000"));
+        assert!(!is_datetime("today"));
+    }
+
+    #[test]
+    fn test_format_date() {
+        let tz = Timezone::from(Tz::America__Los_Angeles);
+        let today = today_date(&tz).unwrap();
+        let format = |days: i64| format_date(&(today + chrono::Duration::days(days)), &tz).unwrap();
 
-    // #[test]
-    // fn test_is_date() {
-    //     assert!(is_date("2022-10-05"));
-    //     assert!(!is_date("22-10-05"));
-    //     assert!(!is_date("2022-10-05 24:02"));
-    //     assert!(!is_date("today"));
-    // }
-
-    // #[test]
-    // fn test_is_datetime() {
-    //     assert!(!is_datetime("2022-10-05"));
-    //     assert!(!is_datetime("22-10-05"));
-    //     assert!(is_datetime("2022-10-05 24:02"));
-    //     assert!(!is_datetime("today"));
-    // }
+        assert_eq!(format(0), "Today");
+        assert_eq!(format(1), "Tomorrow");
+        assert_eq!(format(-1), "1 day overdue");
+        assert_eq!(format(-3), "3 days overdue");
+        assert_eq!(
+            format(3),
+            (today + chrono::Duration::days(3)).format("%A").to_string()
+        );
+    }
+
+    #[test]
+    fn test_format_countdown() {
+        let tz = Timezone::from(Tz::America__Los_Angeles);
+        let today = today_date(&tz).unwrap();
+        let format =
+            |days: i64| format_countdown(&(today + chrono::Duration::days(days)), &tz).unwrap();
+
+        assert_eq!(format(0), "Today");
+        assert_eq!(format(5), "5 days left");
+        assert_eq!(format(-2), "2 days overdue");
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        let tz = Timezone::from(Tz::America__Los_Angeles);
+        let tomorrow = today_date(&tz).unwrap() + chrono::Duration::days(1);
+        let datetime = tz
+            .from_local_datetime(&tomorrow.and_hms_opt(14, 30, 0).unwrap())
+            .unwrap();
+
+        assert_eq!(format_datetime(&datetime, &tz).unwrap(), "Tomorrow 14:30");
+    }
+
+    #[test]
+    fn test_format_minutes() {
+        assert_eq!(format_minutes(0), "0m");
+        assert_eq!(format_minutes(45), "45m");
+        assert_eq!(format_minutes(150), "2h 30m");
+        assert_eq!(format_minutes(1500), "1d 1h");
+    }
 
     #[test]
     fn test_timezone_from_string() {
         assert_eq!(
             timezone_from_str("America/Los_Angeles"),
-            Ok(Tz::America__Los_Angeles),
+            Ok(Timezone::Named(Tz::America__Los_Angeles)),
+        );
+
+        assert_eq!(
+            timezone_from_str("GMT -7:00"),
+            Ok(Timezone::Fixed(FixedOffset::west_opt(7 * 3600).unwrap())),
+        );
+        assert_eq!(
+            timezone_from_str("GMT +5:30"),
+            Ok(Timezone::Fixed(FixedOffset::east_opt(330 * 60).unwrap())),
         );
+        assert_eq!(timezone_from_str("GMT -3:30").unwrap().name(), "GMT -3:30");
+        assert!(timezone_from_str("Mars/Olympus_Mons").is_err());
+        assert!(timezone_from_str("GMT +25:00").is_err());
+        assert!(timezone_from_str("GMT +5:75").is_err());
+        assert!(timezone_from_str("GMT +5:60").is_err());
+    }
+
+    /// Every offset in use is a multiple of 15 minutes between -12:00 and +14:00
+    fn all_offsets() -> impl Iterator<Item = i32> {
+        (-12 * 4..=14 * 4).map(|quarters| quarters * 15 * 60)
+    }
+
+    #[test]
+    fn test_gmt_offsets_round_trip() {
+        for seconds in all_offsets() {
+            let expected = Timezone::Fixed(FixedOffset::east_opt(seconds).unwrap());
+            let name = expected.name();
+            assert_eq!(timezone_from_str(&name), Ok(expected), "{name}");
+            assert_eq!(timezone_from_str(&name.replace(':', "")), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn test_now_and_age_in_all_offsets() {
+        for seconds in all_offsets() {
+            let tz = Timezone::Fixed(FixedOffset::east_opt(seconds).unwrap());
+            let now = now(&tz).unwrap();
+            assert_eq!(now.offset().fix().local_minus_utc(), seconds);
+            assert_eq!(
+                now.naive_local() - now.naive_utc(),
+                chrono::Duration::seconds(seconds.into())
+            );
+
+            let earlier = now - chrono::Duration::minutes(90);
+            let age = age_in_minutes(earlier, &tz).unwrap();
+            assert!((90..=91).contains(&age), "{} is {age}", tz.name());
+
+            // Datetimes recorded in another timezone still compare by instant
+            let elsewhere = Timezone::from(Tz::Asia__Kathmandu);
+            let age = age_in_minutes(earlier.with_timezone(&elsewhere), &tz).unwrap();
+            assert!((90..=91).contains(&age), "{} is {age}", tz.name());
 
-        assert_eq!(timezone_from_str("GMT -7:00"), Ok(Tz::Etc__GMTPlus7),);
+            assert_eq!(today_date(&tz).unwrap(), now.date_naive());
+        }
     }
 }
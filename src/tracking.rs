@@ -0,0 +1,250 @@
+//! Tracks how long each task is on screen before it is completed or skipped
+use crate::account;
+use crate::error::Error;
+use crate::projects::Projects;
+use crate::session::ActionKind;
+use crate::tasks::Task;
+use crate::time::Timezone;
+use crate::{time, AppState};
+use chrono::{DateTime, NaiveDate};
+use std::sync::Arc;
+
+/// Oldest entries are dropped beyond this
+const MAX_TIME_ENTRIES: usize = 1000;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CurrentTask {
+    pub task_id: String,
+    pub shown_at: DateTime<Timezone>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TimeEntry {
+    pub task: Task,
+    /// None when the interval was cut short by a break
+    pub kind: Option<ActionKind>,
+    pub started_at: DateTime<Timezone>,
+    pub ended_at: DateTime<Timezone>,
+}
+
+/// Time spent on tasks sharing a project or label
+pub struct Total {
+    pub name: String,
+    pub task_count: usize,
+    pub minutes: u32,
+}
+
+impl TimeEntry {
+    pub fn minutes(&self) -> u32 {
+        self.ended_at
+            .signed_duration_since(self.started_at)
+            .num_minutes()
+            .max(0) as u32
+    }
+}
+
+impl Total {
+    pub fn time_spent(&self) -> String {
+        time::format_minutes(self.minutes)
+    }
+}
+
+/// Closes the interval for the task acted on and opens one for the next task in the queue.
+/// A break closes the interval of the task on screen, it is opened again once the break is over.
+/// Returns when the next task was first shown.
+pub async fn record(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    action: Option<&(ActionKind, Task)>,
+    queue: &[Task],
+    on_break: bool,
+) -> Result<Option<DateTime<Timezone>>, Error> {
+    let mut account_state = account::get_or_create(app_state, token).await?;
+    let now = time::now(timezone)?;
+
+    if let Some(current) = &account_state.current_task {
+        let closed = match action {
+            Some((kind, task)) if current.task_id == task.id => Some((Some(*kind), task)),
+            _ if on_break => queue
+                .iter()
+                .find(|t| t.id == current.task_id)
+                .map(|task| (None, task)),
+            _ => None,
+        };
+        if let Some((kind, task)) = closed {
+            account_state.time_entries.push(TimeEntry {
+                task: task.clone(),
+                kind,
+                started_at: current.shown_at,
+                ended_at: now,
+            });
+        }
+    }
+    let overflow = account_state
+        .time_entries
+        .len()
+        .saturating_sub(MAX_TIME_ENTRIES);
+    account_state.time_entries.drain(..overflow);
+
+    // Tasks are hidden during a break so their time should not be counted
+    let next_task = if on_break { None } else { queue.first() };
+    account_state.current_task = match (next_task, account_state.current_task) {
+        (Some(task), Some(current)) if current.task_id == task.id => Some(current),
+        (Some(task), _) => Some(CurrentTask {
+            task_id: task.id.clone(),
+            shown_at: now,
+        }),
+        (None, _) => None,
+    };
+    let shown_at = account_state.current_task.as_ref().map(|c| c.shown_at);
+
+    account::save(app_state, token, account_state).await?;
+    Ok(shown_at)
+}
+
+/// Total time spent on a task across every time it was shown
+pub async fn minutes_for_task(
+    app_state: &Arc<AppState>,
+    token: &str,
+    task_id: &str,
+) -> Result<u32, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    Ok(account_state
+        .time_entries
+        .iter()
+        .filter(|e| e.task.id == task_id)
+        .map(TimeEntry::minutes)
+        .sum())
+}
+
+/// Comment posted to Todoist when time logging is turned on
+pub fn time_log_comment(minutes: u32, date: NaiveDate, notes: &str) -> String {
+    let mut comment = format!(
+        "SingleTask: spent {} on {}",
+        time::format_minutes(minutes),
+        date.format("%Y-%m-%d")
+    );
+    let notes = notes.trim();
+    if !notes.is_empty() {
+        comment.push_str("\n\n");
+        comment.push_str(notes);
+    }
+    comment
+}
+
+pub fn totals_by_project(entries: &[TimeEntry], projects: &Projects) -> Vec<Total> {
+    totals(entries, |entry| {
+        vec![projects.project_name(&entry.task.project_id)]
+    })
+}
+
+/// Entries with several labels count towards each of them
+pub fn totals_by_label(entries: &[TimeEntry]) -> Vec<Total> {
+    totals(entries, |entry| {
+        if entry.task.labels.is_empty() {
+            vec![String::from("No label")]
+        } else {
+            entry.task.labels.clone()
+        }
+    })
+}
+
+/// Sorted with the most time spent first
+fn totals(entries: &[TimeEntry], names: impl Fn(&TimeEntry) -> Vec<String>) -> Vec<Total> {
+    let mut totals: Vec<(Total, Vec<&str>)> = Vec::new();
+    for entry in entries {
+        for name in names(entry) {
+            let index = match totals.iter().position(|(t, _)| t.name == name) {
+                Some(index) => index,
+                None => {
+                    totals.push((
+                        Total {
+                            name,
+                            task_count: 0,
+                            minutes: 0,
+                        },
+                        Vec::new(),
+                    ));
+                    totals.len() - 1
+                }
+            };
+            let (total, task_ids) = &mut totals[index];
+            total.minutes += entry.minutes();
+            if !task_ids.contains(&entry.task.id.as_str()) {
+                task_ids.push(entry.task.id.as_str());
+                total.task_count += 1;
+            }
+        }
+    }
+
+    let mut totals = totals.into_iter().map(|(t, _)| t).collect::<Vec<Total>>();
+    totals.sort_by_key(|t| std::cmp::Reverse(t.minutes));
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::Project;
+    use crate::responses::fixture_task;
+    use chrono_tz::Tz;
+
+    fn entry(id: &str, project_id: &str, labels: &[&str], minutes: i64) -> TimeEntry {
+        let ended_at = time::now(&Timezone::from(Tz::UTC)).unwrap();
+        TimeEntry {
+            task: fixture_task(id, |task| {
+                task.project_id = project_id.to_string();
+                task.labels = labels.iter().map(|l| l.to_string()).collect();
+            }),
+            kind: Some(ActionKind::Completed),
+            started_at: ended_at - chrono::Duration::minutes(minutes),
+            ended_at,
+        }
+    }
+
+    #[test]
+    fn test_time_log_comment() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 14).unwrap();
+        assert_eq!(
+            time_log_comment(95, date, "  "),
+            "SingleTask: spent 1h 35m on 2024-05-14"
+        );
+        assert_eq!(
+            time_log_comment(5, date, "Waiting on Bob\n"),
+            "SingleTask: spent 5m on 2024-05-14\n\nWaiting on Bob"
+        );
+    }
+
+    #[test]
+    fn test_totals() {
+        let entries = [
+            entry("1", "work", &["email"], 10),
+            entry("1", "work", &["email"], 5),
+            entry("2", "home", &[], 30),
+            entry("3", "work", &["email", "phone"], 20),
+        ];
+
+        let projects = Projects {
+            projects: vec![Project {
+                id: String::from("work"),
+                name: String::from("Work"),
+                color: String::from("blue"),
+                is_archived: false,
+            }],
+            sections: Vec::new(),
+        };
+
+        let by_project = totals_by_project(&entries, &projects);
+        assert_eq!(by_project[0].name, "Work");
+        assert_eq!(by_project[0].minutes, 35);
+        assert_eq!(by_project[0].task_count, 2);
+        assert_eq!(by_project[1].name, "home");
+
+        let by_label = totals_by_label(&entries);
+        assert_eq!(by_label[0].name, "email");
+        assert_eq!(by_label[0].minutes, 35);
+        assert_eq!(by_label[1].name, "No label");
+        assert_eq!(by_label[2].name, "phone");
+    }
+}
@@ -0,0 +1,175 @@
+//! Deleted tasks are kept for a few minutes so that the deletion can be undone
+use crate::error::{self, Error};
+use crate::session::ActionKind;
+use crate::tasks::{self, Task};
+use crate::time::{self, Timezone};
+use crate::{account, AppState, UserState};
+use chrono::DateTime;
+use std::sync::Arc;
+
+pub const UNDO_MINUTES: i64 = 5;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DeletedTask {
+    pub task: Task,
+    /// Open subtasks Todoist deleted along with the task, each after its parent
+    pub subtasks: Vec<Task>,
+    /// The subtasks that were in the queue, they go back into it
+    pub queued_subtask_ids: Vec<String>,
+    /// The filter queue it was deleted from
+    pub key: String,
+    pub deleted_at: DateTime<Timezone>,
+}
+
+impl DeletedTask {
+    pub fn is_undoable(&self, timezone: &Timezone) -> Result<bool, Error> {
+        Ok(time::age_in_minutes(self.deleted_at, timezone)? < UNDO_MINUTES)
+    }
+}
+
+/// Deletes the task in Todoist, removes it and its subtasks from the queue and keeps a copy
+/// of them for undoing
+pub async fn delete(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let db = &app_state.db;
+    let user_state: Option<UserState> = db.begin(false).await.get(key.to_string())?;
+    let Some(user_state) = user_state else {
+        return Ok(());
+    };
+    // Absent when the page is reloaded after the task was already deleted
+    let Some(task) = user_state.tasks.iter().find(|t| t.id == task_id).cloned() else {
+        return Ok(());
+    };
+    let project_tasks = tasks::project_tasks(token, &task.project_id, test_server_url).await?;
+    let deleted_ids = tasks::with_descendant_ids(&project_tasks, task_id);
+    let subtasks = deleted_ids
+        .iter()
+        .skip(1)
+        .filter_map(|id| project_tasks.iter().find(|t| &t.id == id))
+        .cloned()
+        .collect();
+    tasks::delete_task(token, task_id, test_server_url).await?;
+
+    let queued_subtask_ids = deleted_ids
+        .iter()
+        .skip(1)
+        .filter(|id| user_state.tasks.iter().any(|t| &t.id == *id))
+        .cloned()
+        .collect();
+    let tasks = user_state
+        .tasks
+        .iter()
+        .filter(|t| !deleted_ids.contains(&t.id))
+        .cloned()
+        .collect();
+    let mut tx = db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+
+    let account_state = account::get_or_create(app_state, token).await?;
+    let account_state = account::AccountState {
+        deleted: Some(Box::new(DeletedTask {
+            task,
+            subtasks,
+            queued_subtask_ids,
+            key: key.to_string(),
+            deleted_at: time::now(timezone)?,
+        })),
+        ..account_state
+    };
+    account::save(app_state, token, account_state).await
+}
+
+/// Recreates the deleted task and its subtasks, they go at the front of the queue they
+/// came from. The copy gets a new id, so the deletion is also dropped from the session.
+pub async fn undo(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    let Some(deleted) = account_state.deleted.clone() else {
+        return Ok(());
+    };
+    if deleted.task.id != task_id || !deleted.is_undoable(timezone)? {
+        return Ok(());
+    }
+    let mut recreated = vec![deleted.task.clone()];
+    recreated.extend(deleted.subtasks.iter().cloned());
+    let new_ids = tasks::recreate_tasks(token, &recreated, test_server_url).await?;
+    if !new_ids.contains_key(&deleted.task.id) {
+        return Err(error::new("undo", "Todoist did not recreate the task"));
+    }
+    account::save(
+        app_state,
+        token,
+        account::AccountState {
+            deleted: None,
+            ..account_state
+        },
+    )
+    .await?;
+
+    let db = &app_state.db;
+    let user_state: Option<UserState> = db.begin(false).await.get(deleted.key.clone())?;
+    let Some(user_state) = user_state else {
+        return Ok(());
+    };
+    // Parents come before their subtasks, as grouping and children first expect
+    let mut tasks: Vec<Task> = recreated
+        .into_iter()
+        .filter(|t| t.id == deleted.task.id || deleted.queued_subtask_ids.contains(&t.id))
+        .map(|t| Task {
+            id: new_ids.get(&t.id).cloned().unwrap_or(t.id),
+            parent_id: t
+                .parent_id
+                .map(|parent_id| new_ids.get(&parent_id).cloned().unwrap_or(parent_id)),
+            ..t
+        })
+        .collect();
+    tasks.extend(user_state.tasks.iter().cloned());
+    let session = user_state.session.clone().map(|mut session| {
+        session
+            .actions
+            .retain(|a| !(a.kind == ActionKind::Deleted && a.task.id == deleted.task.id));
+        session
+    });
+    let mut tx = db.begin(true).await;
+    tx.set(
+        deleted.key,
+        UserState {
+            tasks,
+            session,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// The last deleted task while it can still be restored
+pub async fn undoable(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+) -> Result<Option<Task>, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    match account_state.deleted {
+        Some(deleted) if deleted.is_undoable(timezone)? => Ok(Some(deleted.task)),
+        _ => Ok(None),
+    }
+}
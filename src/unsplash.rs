@@ -1,9 +1,9 @@
 use crate::request;
 use crate::time;
+use crate::time::Timezone;
 use crate::Env;
 use crate::UserState;
 use crate::{error::Error, AppState};
-use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -37,7 +37,7 @@ pub struct User {
 pub async fn cached_get_random(
     app_state: &Arc<AppState>,
     user_state: &UserState,
-    timezone: &Tz,
+    timezone: &Timezone,
     key: String,
 ) -> Result<Unsplash, Error> {
     let updated_at = user_state.unsplash_updated_at;
@@ -1,11 +1,15 @@
-use chrono_tz::Tz;
+use crate::time::Timezone;
+use chrono::{DateTime, Datelike};
 use serde::Deserialize;
 use serde_json::json;
 use std::sync::Arc;
 
-use crate::{error::Error, request, time, AppState, UserState};
+use crate::{account, error::Error, request, time, AppState, UserState};
 
 const SYNC_URL: &str = "/sync/v9/sync";
+const STATS_URL: &str = "/sync/v9/completed/get_stats";
+const CACHE_STATS_MAX_AGE_MINUTES: i64 = 15;
+const CACHE_USER_MAX_AGE_MINUTES: i64 = 15;
 
 /// https://developer.todoist.com/sync/v9/#user
 #[derive(Deserialize, Debug)]
@@ -13,42 +17,174 @@ pub struct SyncResponse {
     user: User,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct User {
     tz_info: TzInfo,
+    pub daily_goal: u32,
+    /// ISO weekdays, 1 is Monday
+    pub days_off: Vec<u32>,
+    pub features: Features,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct TzInfo {
     timezone: String,
 }
 
-/// Fetches from cache or API
-pub async fn cached_get_timezone(
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Features {
+    pub karma_vacation: bool,
+}
+
+/// https://developer.todoist.com/sync/v9/#get-productivity-stats
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub days_items: Vec<DayItem>,
+    pub goals: StatsGoals,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DayItem {
+    pub date: String,
+    pub total_completed: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatsGoals {
+    pub current_daily_streak: Streak,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Streak {
+    pub count: u32,
+}
+
+/// Progress towards the Todoist daily goal
+pub struct Goals {
+    pub daily_goal: u32,
+    pub completed_today: u32,
+    pub streak: u32,
+    pub vacation: bool,
+    pub day_off: bool,
+}
+
+impl User {
+    pub fn timezone(&self) -> Result<Timezone, Error> {
+        time::timezone_from_str(&self.tz_info.timezone)
+    }
+}
+
+impl Goals {
+    pub fn is_met(&self) -> bool {
+        self.completed_today >= self.daily_goal
+    }
+}
+
+/// The timezone chosen for the filter with the timezone parameter wins, then the one in the
+/// preferences and last the one Todoist reports
+pub async fn resolve_timezone(
     app_state: &Arc<AppState>,
     user_state: &UserState,
+    preference: Option<Timezone>,
+    token: &str,
+    test_server_url: &Option<String>,
+) -> Result<Timezone, Error> {
+    match user_state.timezone.or(preference) {
+        Some(timezone) => Ok(timezone),
+        None => cached_get_user(app_state, token, test_server_url)
+            .await?
+            .timezone(),
+    }
+}
+
+/// Fetches from cache or API, refreshed now and then so that changes to the goal,
+/// vacation mode and days off show up
+pub async fn cached_get_user(
+    app_state: &Arc<AppState>,
     token: &str,
-    key: &str,
     test_server_url: &Option<String>,
-) -> Result<Tz, Error> {
-    if let Some(timezone) = user_state.timezone {
-        Ok(timezone)
-    } else {
-        let User {
-            tz_info: TzInfo { timezone },
-        } = get_user_data(token, test_server_url).await?;
-        let tz = time::timezone_from_str(&timezone)?;
-
-        let db = &app_state.clone().db;
-        let mut tx = db.begin(true).await;
-        let user_state = UserState {
-            timezone: Some(tz),
-            ..user_state.clone()
-        };
-        tx.set(key.to_string(), user_state)?;
-        tx.commit()?;
-
-        Ok(tz)
+) -> Result<User, Error> {
+    let account_state = account::get_or_create(app_state, token).await?;
+    if let (Some(user), Some(updated_at)) = (account_state.user, account_state.user_updated_at) {
+        if time::age_in_minutes(updated_at, &updated_at.timezone())? < CACHE_USER_MAX_AGE_MINUTES {
+            return Ok(user);
+        }
+    }
+
+    let user = get_user_data(token, test_server_url).await?;
+    let account_state = account::get_or_create(app_state, token).await?;
+    let account_state = account::AccountState {
+        user: Some(user.clone()),
+        user_updated_at: Some(time::now(&user.timezone()?)?),
+        ..account_state
+    };
+    account::save(app_state, token, account_state).await?;
+
+    Ok(user)
+}
+
+/// Fetches stats from cache or API, `refresh` skips the cache after completing a task.
+/// None when they could not be fetched, the goal is left off the page.
+pub async fn cached_get_goals(
+    app_state: &Arc<AppState>,
+    token: &str,
+    timezone: &Timezone,
+    refresh: bool,
+    test_server_url: &Option<String>,
+) -> Result<Option<Goals>, Error> {
+    let user = match cached_get_user(app_state, token, test_server_url).await {
+        Ok(user) => user,
+        Err(error) => {
+            tracing::warn!("Could not fetch user for goals: {error:?}");
+            return Ok(None);
+        }
+    };
+    let account_state = account::get_or_create(app_state, token).await?;
+
+    let stats = match (account_state.stats, account_state.stats_updated_at) {
+        (Some(stats), Some(updated_at))
+            if !refresh
+                && time::age_in_minutes(updated_at, timezone)? < CACHE_STATS_MAX_AGE_MINUTES =>
+        {
+            stats
+        }
+        _ => {
+            let stats = match get_stats(token, test_server_url).await {
+                Ok(stats) => stats,
+                Err(error) => {
+                    tracing::warn!("Could not fetch stats for goals: {error:?}");
+                    return Ok(None);
+                }
+            };
+            let account_state = account::get_or_create(app_state, token).await?;
+            let account_state = account::AccountState {
+                stats: Some(stats.clone()),
+                stats_updated_at: Some(time::now(timezone)?),
+                ..account_state
+            };
+            account::save(app_state, token, account_state).await?;
+            stats
+        }
+    };
+
+    Ok(Some(goals(&user, &stats, time::now(timezone)?)))
+}
+
+fn goals(user: &User, stats: &Stats, now: DateTime<Timezone>) -> Goals {
+    let today = now.format("%Y-%m-%d").to_string();
+    let completed_today = stats
+        .days_items
+        .iter()
+        .find(|d| d.date == today)
+        .map(|d| d.total_completed)
+        .unwrap_or_default();
+
+    Goals {
+        daily_goal: user.daily_goal,
+        completed_today,
+        streak: stats.goals.current_daily_streak.count,
+        vacation: user.features.karma_vacation,
+        day_off: user.days_off.contains(&now.weekday().number_from_monday()),
     }
 }
 
@@ -59,7 +195,38 @@ pub async fn get_user_data(token: &str, test_server: &Option<String>) -> Result<
     sync_json_to_user(json)
 }
 
+pub async fn get_stats(token: &str, test_server: &Option<String>) -> Result<Stats, Error> {
+    let json = request::get_todoist_rest(token, STATS_URL, test_server.clone()).await?;
+    let stats: Stats = serde_json::from_str(&json)?;
+    Ok(stats)
+}
+
 pub fn sync_json_to_user(json: String) -> Result<User, Error> {
     let sync_response: SyncResponse = serde_json::from_str(&json)?;
     Ok(sync_response.user)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::ResponseFromFile;
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+
+    #[tokio::test]
+    async fn test_goals() {
+        let user = sync_json_to_user(ResponseFromFile::Sync.read().await).unwrap();
+        let stats: Stats = serde_json::from_str(&ResponseFromFile::Stats.read().await).unwrap();
+        let now = Timezone::from(Tz::America__Vancouver)
+            .with_ymd_and_hms(2024, 5, 14, 9, 0, 0)
+            .unwrap();
+
+        let goals = goals(&user, &stats, now);
+        assert_eq!(goals.daily_goal, 20);
+        assert_eq!(goals.completed_today, 7);
+        assert_eq!(goals.streak, 12);
+        assert!(!goals.vacation);
+        assert!(!goals.day_off);
+        assert!(!goals.is_met());
+    }
+}
@@ -0,0 +1,57 @@
+use crate::account;
+use crate::error::Error;
+use crate::projects;
+use crate::tracking::{self, Total};
+use crate::unsplash;
+use crate::unsplash::Unsplash;
+use crate::user;
+use crate::views::process::fetch_parameter;
+use crate::{AppState, Link};
+use askama::Template;
+use axum::extract::State;
+use axum::{extract::Query, response::Html, routing::get, Router};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn routes(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/history", get(history))
+        .with_state(app_state)
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryTemplate {
+    title: String,
+    navigation: Vec<Link>,
+    by_project: Vec<Total>,
+    by_label: Vec<Total>,
+    unsplash: Unsplash,
+}
+
+async fn history(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Error> {
+    let token = fetch_parameter(&params, "token")?;
+    let test_server_url = &app_state.test_server_url;
+    let account_state = account::get_or_create(&app_state, &token).await?;
+    let timezone = match account_state.preferences.timezone {
+        Some(timezone) => timezone,
+        None => user::cached_get_user(&app_state, &token, test_server_url)
+            .await?
+            .timezone()?,
+    };
+    let projects =
+        projects::cached_get_projects(&app_state, &token, &timezone, test_server_url).await?;
+
+    let index = HistoryTemplate {
+        title: "History".into(),
+        navigation: crate::get_nav(),
+        by_project: tracking::totals_by_project(&account_state.time_entries, &projects),
+        by_label: tracking::totals_by_label(&account_state.time_entries),
+        unsplash: unsplash::stub(),
+    };
+
+    Ok(Html(index.render()?))
+}
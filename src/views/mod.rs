@@ -1,3 +1,7 @@
+pub mod history;
 pub mod index;
+pub mod preview;
 pub mod process;
+pub mod settings;
 pub mod shortcuts;
+pub mod summary;
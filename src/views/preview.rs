@@ -0,0 +1,98 @@
+use crate::account;
+use crate::error::Error;
+use crate::tasks::{self, Task};
+use crate::unsplash;
+use crate::unsplash::Unsplash;
+use crate::views::process::{fetch_filter, fetch_parameter, markdown_to_html};
+use crate::{time, AppState, Link};
+use askama::Template;
+use axum::extract::State;
+use axum::{extract::Query, response::Html, routing::get, Router};
+use futures::future::join_all;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How many tasks to show as a sample of what is coming
+const SAMPLE_SIZE: usize = 5;
+
+pub fn routes(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/preview", get(preview))
+        .with_state(app_state)
+}
+
+struct FilterPreview {
+    filter: String,
+    count: usize,
+    /// Set when Todoist rejected the filter
+    error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "preview.html")]
+struct PreviewTemplate {
+    title: String,
+    navigation: Vec<Link>,
+    token: String,
+    filter: String,
+    filters: Vec<FilterPreview>,
+    is_valid: bool,
+    task_count: usize,
+    total_duration: String,
+    sample: Vec<Task>,
+    unsplash: Unsplash,
+}
+
+async fn preview(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Error> {
+    let token = fetch_parameter(&params, "token")?;
+    let preferences = account::get_or_create(&app_state, &token)
+        .await?
+        .preferences;
+    let filter = fetch_filter(&params, &preferences)?;
+    let test_server_url = &app_state.test_server_url;
+
+    let split_filters = tasks::split_filter(&filter);
+    let handles = split_filters
+        .iter()
+        .map(|f| tasks::tasks_for_filter(&token, f, test_server_url.clone()));
+    let results = join_all(handles).await;
+
+    let mut filters = Vec::new();
+    let mut all_tasks: Vec<Task> = Vec::new();
+    for (f, result) in split_filters.into_iter().zip(results) {
+        match result {
+            Ok(tasks) => {
+                filters.push(FilterPreview {
+                    filter: f.to_string(),
+                    count: tasks.len(),
+                    error: None,
+                });
+                all_tasks.extend(tasks);
+            }
+            Err(Error::BadFilter { message, .. }) => filters.push(FilterPreview {
+                filter: f.to_string(),
+                count: 0,
+                error: Some(message),
+            }),
+            Err(error) => return Err(error),
+        }
+    }
+
+    let index = PreviewTemplate {
+        title: "Preview".into(),
+        navigation: crate::get_nav(),
+        token,
+        filter,
+        is_valid: filters.iter().all(|f| f.error.is_none()),
+        filters,
+        task_count: all_tasks.len(),
+        total_duration: time::format_minutes(tasks::total_duration_minutes(&all_tasks)),
+        sample: markdown_to_html(all_tasks.into_iter().take(SAMPLE_SIZE).collect()),
+        unsplash: unsplash::stub(),
+    };
+
+    Ok(Html(index.render()?))
+}
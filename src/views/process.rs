@@ -1,17 +1,28 @@
+use crate::account;
+use crate::comments::{self, Comment};
 use crate::error::Error;
-use crate::tasks::Task;
+use crate::pomodoro::{self, Pomodoro};
+use crate::preferences::{Preferences, SkipMode, Theme};
+use crate::projects::{self, Breadcrumb, Choice, Destination};
+use crate::session::{self, ActionKind, Progress, Session, Summary};
+use crate::skips::{self, Alert};
+use crate::snooze::{self, SnoozeFor};
 use crate::tasks::{self, Priority};
+use crate::tasks::{Due, Order, Task};
+use crate::time::Timezone;
+use crate::tracking;
+use crate::undo;
 use crate::unsplash;
 use crate::unsplash::Unsplash;
-use crate::user;
+use crate::user::{self, Goals};
 use crate::{time, AppState, Link, UserState};
 use askama::Template;
 use axum::extract::State;
-use axum::http::StatusCode;
 use axum::{extract::Query, response::Html, routing::get, Router};
-use chrono_tz::Tz;
+use chrono::DateTime;
 use comrak::Options;
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
 const CACHE_TASKS_MAX_AGE_MINUTES: i64 = 15;
@@ -29,8 +40,57 @@ struct ProcessWithTask {
     navigation: Vec<Link>,
     token: String,
     content_color_class: String,
+    breadcrumb: Breadcrumb,
+    comments: Vec<Comment>,
+    /// Set when the comments could not be fetched
+    comments_unavailable: bool,
+    /// The task before its Markdown was rendered, for the edit form
+    editable: Task,
+    /// Projects and sections the task can be moved to
+    choices: Vec<Choice>,
+    /// Shown right after a deletion so that it can be undone
+    deleted: Option<Task>,
+    /// Set when the task keeps being skipped, along with ways to deal with it
+    skip_alert: Option<Alert>,
+    postpone_due: &'static str,
+    /// Shown as a checklist, each can be completed on its own
+    subtasks: Vec<Task>,
+    /// Suggested content for the task that follows on from this one
+    follow_up: String,
+    follow_up_due: &'static str,
     task: Task,
+    due: Option<Due>,
+    deadline: Option<Due>,
     filter: String,
+    session: Session,
+    progress: Progress,
+    /// When the task was first shown
+    shown_at: DateTime<Timezone>,
+    elapsed: String,
+    pomodoro: Option<Pomodoro>,
+    pomodoro_remaining: String,
+    /// Work intervals spent on this task
+    pomodoro_count: u32,
+    /// None when the stats could not be fetched
+    goals: Option<Goals>,
+    /// Text that was just sent to quick add
+    captured: Option<String>,
+    theme: Theme,
+    unsplash: Unsplash,
+}
+
+#[derive(Template)]
+#[template(path = "process_break.html")]
+struct ProcessBreak {
+    title: String,
+    navigation: Vec<Link>,
+    token: String,
+    filter: String,
+    pomodoro: Pomodoro,
+    remaining: String,
+    /// Text that was just sent to quick add
+    captured: Option<String>,
+    theme: Theme,
     unsplash: Unsplash,
 }
 
@@ -41,6 +101,16 @@ struct ProcessNoTask {
     navigation: Vec<Link>,
     token: String,
     filter: String,
+    summary: Option<Summary>,
+    /// When the next held back task is due
+    next_up: Option<String>,
+    /// Skipped tasks that can be gone through again
+    skipped_pile: usize,
+    /// Shown right after a deletion so that it can be undone
+    deleted: Option<Task>,
+    /// Text that was just sent to quick add
+    captured: Option<String>,
+    theme: Theme,
     unsplash: Unsplash,
 }
 
@@ -48,84 +118,258 @@ async fn process(
     State(app_state): State<Arc<AppState>>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Html<String>, Error> {
-    let has_complete_task_id = params.contains_key("complete_task_id");
+    let complete_task_id = params.get("complete_task_id").map(String::as_str);
     let skip_task_id = params.get("skip_task_id");
-    let filter = fetch_parameter(&params, "filter")?;
     let token = fetch_parameter(&params, "token")?;
+    let preferences = account::get_or_create(&app_state, &token)
+        .await?
+        .preferences;
+    let filter = fetch_filter(&params, &preferences)?;
     let key = format!("{token}{filter}");
     let test_server_url = &app_state.clone().test_server_url;
     let user_state = get_or_create_user_state(app_state.clone(), &key).await?;
-    let timezone =
-        user::cached_get_timezone(&app_state, &user_state, &token, &key, test_server_url).await?;
-    let unsplash = unsplash::cached_get_random(&app_state, &user_state, &timezone, key).await?;
+    let user_state = update_options(&app_state, &key, user_state, &params).await?;
+    let timezone = user::resolve_timezone(
+        &app_state,
+        &user_state,
+        preferences.timezone,
+        &token,
+        test_server_url,
+    )
+    .await?;
+    let unsplash =
+        unsplash::cached_get_random(&app_state, &user_state, &timezone, key.clone()).await?;
     let mut title = filter.clone();
     title.truncate(20);
 
-    if !has_complete_task_id {
-        let tasks = get_tasks(
-            app_state,
-            &token,
-            &filter,
-            &timezone,
-            None,
-            skip_task_id,
-            test_server_url,
-        )
-        .await;
-        if let Some(task) = tasks?.first() {
-            let index = ProcessWithTask {
-                title,
-                navigation: crate::get_nav(),
-                token: token.to_owned(),
-                filter: filter.to_owned(),
-                content_color_class: get_content_color_class(task),
-                task: task.clone(),
-                unsplash,
-            };
-            Ok(Html(index.render()?))
-        } else {
-            let index = ProcessNoTask {
+    let move_task_id = params.get("move_task_id");
+    let delete_task_id = params.get("delete_task_id");
+    let snooze_task_id = params.get("snooze_task_id");
+    let postpone_task_id = params.get("postpone_task_id");
+    change_tasks(&app_state, &key, &token, &filter, &timezone, &params).await?;
+    let captured = capture(&token, &params, test_server_url).await?;
+
+    let handle = complete_task_id
+        .map(|task_id| tasks::spawn_complete_task(&token, task_id, test_server_url));
+    let tasks = get_tasks(
+        app_state.clone(),
+        &token,
+        &filter,
+        &timezone,
+        complete_task_id,
+        skip_task_id,
+        preferences.skip,
+    )
+    .await;
+    if let Some(handle) = handle {
+        let _ = handle.await?;
+    }
+    let mut tasks = tasks?;
+    tasks::sort(&mut tasks, user_state.order.unwrap_or(preferences.order));
+    let cached = get_or_create_user_state(app_state.clone(), &key).await?;
+    let skip_task_ids = cached.skip_task_ids;
+    // A moved task that still matches the filter stays in the queue and is not done yet
+    let move_task_id =
+        move_task_id.filter(|task_id| !cached.tasks.iter().any(|t| &t.id == *task_id));
+    if preferences.skip == SkipMode::EndOfQueue {
+        tasks = tasks::move_to_end(tasks, &skip_task_ids);
+    }
+    let (tasks, next_up) = if user_state.hide_future {
+        tasks::hold_back_future(tasks, &timezone)?
+    } else {
+        (tasks, None)
+    };
+    let (tasks, mut subtasks) = if user_state.children_first {
+        (tasks::children_first(tasks), HashMap::new())
+    } else {
+        tasks::group_subtasks(tasks)
+    };
+
+    let action = complete_task_id
+        .map(|task_id| (ActionKind::Completed, task_id))
+        .or(skip_task_id.map(|task_id| (ActionKind::Skipped, task_id.as_str())))
+        .or(move_task_id.map(|task_id| (ActionKind::Moved, task_id.as_str())))
+        .or(delete_task_id.map(|task_id| (ActionKind::Deleted, task_id.as_str())))
+        .or(snooze_task_id.map(|task_id| (ActionKind::Snoozed, task_id.as_str())))
+        .or(postpone_task_id.map(|task_id| (ActionKind::Postponed, task_id.as_str())))
+        .and_then(|(kind, task_id)| {
+            // Absent when the page is reloaded after the task was already dealt with
+            user_state
+                .tasks
+                .iter()
+                .find(|t| t.id == task_id)
+                .map(|t| (kind, t.clone()))
+        });
+    if let Some((ActionKind::Skipped, task)) = &action {
+        skips::record(&app_state, &token, &timezone, &task.id).await?;
+    }
+    let pomodoro = pomodoro::update(
+        &app_state,
+        &token,
+        &timezone,
+        params.get("pomodoro"),
+        !tasks.is_empty(),
+    )
+    .await?;
+    let on_break = pomodoro.as_ref().is_some_and(Pomodoro::is_on_break);
+    let shown_at = tracking::record(
+        &app_state,
+        &token,
+        &timezone,
+        action.as_ref(),
+        &tasks,
+        on_break,
+    )
+    .await?;
+    let completed = action
+        .as_ref()
+        .filter(|(kind, _)| *kind == ActionKind::Completed)
+        .map(|(_, task)| task.id.clone());
+    let session = session::record(&app_state, &key, &timezone, &tasks, action).await?;
+
+    if let (Some(task_id), Some("on")) = (completed, params.get("log_time").map(String::as_str)) {
+        let minutes = tracking::minutes_for_task(&app_state, &token, &task_id).await?;
+        let date = match &session {
+            Some(session) => session.started_at.date_naive(),
+            None => time::now(&timezone)?.date_naive(),
+        };
+        let notes = params.get("notes").map(String::as_str).unwrap_or_default();
+        let comment = tracking::time_log_comment(minutes, date, notes);
+        // The task is already completed, so the page is still shown
+        if let Err(error) = tasks::add_comment(&token, &task_id, &comment, test_server_url).await {
+            tracing::warn!("Could not log time on task {task_id}: {error:?}");
+        }
+    }
+
+    // Once the queue is empty skipped tasks are either shown again next time or offered as a pile
+    let skipped_pile = match (tasks.is_empty(), preferences.skip) {
+        (true, SkipMode::Session) => {
+            clear_skipped(&app_state, &key).await?;
+            0
+        }
+        (true, SkipMode::Revisit) => {
+            skipped_pile(&app_state, &key, &token, &timezone, &skip_task_ids).await?
+        }
+        _ => 0,
+    };
+    let deleted = match delete_task_id {
+        Some(_) => undo::undoable(&app_state, &token, &timezone).await?,
+        None => None,
+    };
+
+    match (tasks.first(), session, shown_at, pomodoro) {
+        (Some(_), Some(_), None, Some(pomodoro)) if on_break => {
+            let now = time::now(&timezone)?;
+            let index = ProcessBreak {
                 title,
                 navigation: crate::get_nav(),
                 token: token.to_owned(),
                 filter: filter.to_owned(),
+                remaining: pomodoro.remaining(now),
+                pomodoro,
+                captured,
+                theme: preferences.theme,
                 unsplash,
             };
             Ok(Html(index.render()?))
         }
-    } else {
-        let complete_task_id = fetch_parameter(&params, "complete_task_id")?;
-
-        let handle = tasks::spawn_complete_task(&token, &complete_task_id, test_server_url);
-        let tasks = get_tasks(
-            app_state,
-            &token,
-            &filter,
-            &timezone,
-            Some(&complete_task_id),
-            skip_task_id,
-            test_server_url,
-        )
-        .await;
-        let _ = handle.await?;
-
-        if let Some(task) = tasks?.first() {
+        (Some(task), Some(session), Some(shown_at), pomodoro) => {
+            let pomodoro_count = pomodoro::count(&app_state, &token, &task.id).await?;
+            let goals = user::cached_get_goals(
+                &app_state,
+                &token,
+                &timezone,
+                complete_task_id.is_some(),
+                test_server_url,
+            )
+            .await?;
+            let projects =
+                projects::cached_get_projects(&app_state, &token, &timezone, test_server_url)
+                    .await?;
+            // The cached tasks, as the queue has had its Markdown rendered
+            let cached_tasks = get_or_create_user_state(app_state.clone(), &key)
+                .await?
+                .tasks;
+            let breadcrumb = projects::breadcrumb(
+                &app_state,
+                task,
+                &projects,
+                &cached_tasks,
+                &token,
+                &timezone,
+                test_server_url,
+            )
+            .await?;
+            let editable = cached_tasks
+                .iter()
+                .find(|t| t.id == task.id)
+                .unwrap_or(task)
+                .clone();
+            let comments =
+                comments::cached_get_comments(&app_state, &token, task, &timezone, test_server_url)
+                    .await?;
+            let now = time::now(&timezone)?;
             let index = ProcessWithTask {
                 title,
                 navigation: crate::get_nav(),
                 token: token.to_owned(),
                 filter: filter.to_owned(),
                 content_color_class: get_content_color_class(task),
+                breadcrumb,
+                comments_unavailable: comments.is_none(),
+                comments: comments.unwrap_or_default(),
+                choices: projects.choices(),
+                deleted,
+                skip_alert: skips::alert(&app_state, &token, &timezone, &task.id).await?,
+                postpone_due: tasks::POSTPONE_DUE,
+                subtasks: subtasks.remove(&task.id).unwrap_or_default(),
+                follow_up: tasks::follow_up_content(&editable.content),
+                follow_up_due: tasks::FOLLOW_UP_DUE,
+                editable,
+                due: task.due_display(&timezone)?,
+                deadline: task.deadline_display(&timezone)?,
                 task: task.clone(),
+                progress: session.progress(&tasks, preferences.skip),
+                session,
+                elapsed: time::format_minutes(
+                    time::age_in_minutes(shown_at, &timezone)?.max(0) as u32
+                ),
+                shown_at,
+                pomodoro_remaining: pomodoro
+                    .as_ref()
+                    .map(|p| p.remaining(now))
+                    .unwrap_or_default(),
+                pomodoro,
+                pomodoro_count,
+                goals,
+                captured,
+                theme: preferences.theme,
                 unsplash,
             };
             Ok(Html(index.render()?))
-        } else {
+        }
+        (_, session, _, _) => {
+            let summary = match &session {
+                Some(session) => Some(session.summary(&timezone)?),
+                None => None,
+            };
             let index = ProcessNoTask {
                 title,
                 navigation: crate::get_nav(),
                 token: token.to_owned(),
                 filter: filter.to_owned(),
+                summary: summary.map(|summary| Summary {
+                    completed: markdown_to_html(summary.completed),
+                    ..summary
+                }),
+                next_up: match next_up {
+                    Some(next_up) => Some(time::format_datetime(&next_up, &timezone)?),
+                    None => None,
+                },
+                skipped_pile,
+                deleted,
+                captured,
+                theme: preferences.theme,
                 unsplash,
             };
             Ok(Html(index.render()?))
@@ -133,11 +377,21 @@ async fn process(
     }
 }
 
-fn fetch_parameter(params: &HashMap<String, String>, field: &str) -> Result<String, Error> {
+/// The filter parameter, falling back to the default filter from the preferences
+pub fn fetch_filter(
+    params: &HashMap<String, String>,
+    preferences: &Preferences,
+) -> Result<String, Error> {
+    match (params.get("filter"), &preferences.default_filter) {
+        (Some(filter), _) | (None, Some(filter)) => Ok(filter.clone()),
+        (None, None) => fetch_parameter(params, "filter"),
+    }
+}
+
+pub fn fetch_parameter(params: &HashMap<String, String>, field: &str) -> Result<String, Error> {
     params
         .get(field)
-        .ok_or_else(|| Error {
-            code: StatusCode::BAD_REQUEST,
+        .ok_or_else(|| Error::BadRequest {
             message: format!("Missing query parameter: {field}"),
             source: "fetch_parameter".to_string(),
         })
@@ -153,7 +407,10 @@ fn get_content_color_class(task: &Task) -> String {
     }
 }
 
-async fn get_or_create_user_state(app_state: Arc<AppState>, key: &str) -> Result<UserState, Error> {
+pub async fn get_or_create_user_state(
+    app_state: Arc<AppState>,
+    key: &str,
+) -> Result<UserState, Error> {
     let db = &app_state.clone().db;
     let maybe_user_state = db.begin(false).await.get(key.to_string())?;
 
@@ -167,34 +424,503 @@ async fn get_or_create_user_state(app_state: Arc<AppState>, key: &str) -> Result
             unsplash: None,
             unsplash_updated_at: None,
             timezone: None,
+            session: None,
+            order: None,
+            hide_future: false,
+            children_first: false,
+        })
+    }
+}
+
+/// Sends captured text to quick add, the queue is left alone until its cache expires
+async fn capture(
+    token: &str,
+    params: &HashMap<String, String>,
+    test_server_url: &Option<String>,
+) -> Result<Option<String>, Error> {
+    match params.get("capture").map(|text| text.trim()) {
+        Some(text) if !text.is_empty() => {
+            tasks::quick_add(token, text, test_server_url).await?;
+            Ok(Some(text.to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Changes made to tasks from the process page, applied before the queue is read
+async fn change_tasks(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    filter: &str,
+    timezone: &Timezone,
+    params: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let test_server_url = &app_state.test_server_url;
+    if let Some(task_id) = params.get("move_task_id") {
+        let destination = params.get("move_to").ok_or_else(|| Error::BadRequest {
+            source: "change_tasks".to_string(),
+            message: String::from("Choose where to move the task"),
+        })?;
+        let destination = Destination::from_str(destination)?;
+        move_task(
+            app_state,
+            key,
+            token,
+            filter,
+            task_id,
+            &destination,
+            test_server_url,
+        )
+        .await?;
+    }
+    if let Some(task_id) = params.get("delete_task_id") {
+        undo::delete(app_state, key, token, timezone, task_id, test_server_url).await?;
+    }
+    if let (Some(task_id), Some(snooze_for)) =
+        (params.get("snooze_task_id"), params.get("snooze_for"))
+    {
+        let snooze_for = SnoozeFor::from_str(snooze_for).map_err(|_| Error::BadRequest {
+            source: "change_tasks".to_string(),
+            message: format!("Unknown snooze: {snooze_for}"),
+        })?;
+        snooze::snooze(app_state, token, timezone, task_id, snooze_for).await?;
+    }
+    if let Some(task_id) = params.get("postpone_task_id") {
+        postpone(app_state, key, token, timezone, task_id, test_server_url).await?;
+    }
+    if let (Some(task_id), Some(subtasks)) =
+        (params.get("break_down_task_id"), params.get("subtasks"))
+    {
+        break_down(app_state, key, token, task_id, subtasks, test_server_url).await?;
+    }
+    // Doing something about a task starts its skip count again
+    let changed = [
+        "complete_task_id",
+        "edit_task_id",
+        "move_task_id",
+        "delete_task_id",
+        "postpone_task_id",
+        "break_down_task_id",
+    ];
+    for task_id in changed.iter().filter_map(|field| params.get(*field)) {
+        skips::forget(app_state, token, task_id).await?;
+    }
+    if params.contains_key("revisit_skipped") {
+        clear_skipped(app_state, key).await?;
+    }
+    if let Some(task_id) = params.get("undo_delete") {
+        undo::undo(app_state, token, timezone, task_id, test_server_url).await?;
+    }
+    if let Some(task_id) = params.get("complete_subtask_id") {
+        complete_subtask(app_state, key, token, task_id, test_server_url).await?;
+    }
+    if let Some(task_id) = params.get("edit_task_id") {
+        edit_task(app_state, key, token, task_id, params, test_server_url).await?;
+    }
+    if let (Some(task_id), Some(comment)) = (params.get("comment_task_id"), params.get("comment")) {
+        add_comment(app_state, key, token, task_id, comment, test_server_url).await?;
+    }
+    if let (Some(task_id), Some(content)) =
+        (params.get("complete_task_id"), params.get("follow_up"))
+    {
+        let due = params.get("follow_up_due").map(String::as_str);
+        follow_up(
+            app_state,
+            key,
+            token,
+            task_id,
+            content,
+            due,
+            test_server_url,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Moves the task in Todoist and keeps it in the queue only while it still matches the filter
+async fn move_task(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    filter: &str,
+    task_id: &str,
+    destination: &Destination,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the task was already moved
+    if !user_state.tasks.iter().any(|t| t.id == task_id) {
+        return Ok(());
+    }
+    tasks::move_task(token, task_id, destination, test_server_url).await?;
+
+    let matching = tasks::all_tasks(token, filter, test_server_url).await?;
+    let tasks = user_state
+        .tasks
+        .iter()
+        .filter_map(|t| {
+            if t.id == task_id {
+                matching.iter().find(|m| m.id == task_id).cloned()
+            } else {
+                Some(t.clone())
+            }
+        })
+        .collect();
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Sends the edits to Todoist and updates the cached task in place
+async fn edit_task(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    task_id: &str,
+    params: &HashMap<String, String>,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the task was already dealt with
+    let Some(task) = user_state.tasks.iter().find(|t| t.id == task_id) else {
+        return Ok(());
+    };
+    let edited = tasks::apply_edit(task, params)?;
+    if &edited == task {
+        return Ok(());
+    }
+    tasks::update_task(token, &edited, test_server_url).await?;
+
+    let tasks = user_state
+        .tasks
+        .iter()
+        .map(|t| {
+            if t.id == task_id {
+                edited.clone()
+            } else {
+                t.clone()
+            }
+        })
+        .collect();
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Posts a comment on the task and bumps the cached count so that it shows up straight away
+async fn add_comment(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    task_id: &str,
+    comment: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    if comment.trim().is_empty() {
+        return Ok(());
+    }
+    tasks::add_comment(token, task_id, comment.trim(), test_server_url).await?;
+
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    let tasks = user_state
+        .tasks
+        .into_iter()
+        .map(|t| {
+            if t.id == task_id {
+                Task {
+                    comment_count: Some(t.comment_count.unwrap_or_default() + 1),
+                    ..t
+                }
+            } else {
+                t
+            }
         })
+        .collect();
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Pushes the due date far out and takes the task out of the queue
+async fn postpone(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    timezone: &Timezone,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the task was already postponed
+    let Some(task) = user_state.tasks.iter().find(|t| t.id == task_id) else {
+        return Ok(());
+    };
+    let today = time::today_date(timezone)?;
+    tasks::postpone_task(token, task, today, test_server_url).await?;
+
+    let tasks = user_state
+        .tasks
+        .iter()
+        .filter(|t| t.id != task_id)
+        .cloned()
+        .collect();
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Adds a subtask for each line and expires the cache so that they are listed under the task
+async fn break_down(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    task_id: &str,
+    subtasks: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let contents: Vec<&str> = subtasks
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the task was already broken down
+    let Some(task) = user_state.tasks.iter().find(|t| t.id == task_id) else {
+        return Ok(());
+    };
+    if contents.is_empty() {
+        return Ok(());
+    }
+    tasks::add_subtasks(token, task, &contents, test_server_url).await?;
+
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks_updated_at: None,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Forgets the skips so that the skipped tasks are shown again
+async fn clear_skipped(app_state: &Arc<AppState>, key: &str) -> Result<(), Error> {
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    if user_state.skip_task_ids.is_empty() {
+        return Ok(());
+    }
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            skip_task_ids: Vec::new(),
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Number of skipped tasks that would be shown again, leaving out those that are snoozed
+async fn skipped_pile(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    timezone: &Timezone,
+    skip_task_ids: &[String],
+) -> Result<usize, Error> {
+    let tasks = get_or_create_user_state(app_state.clone(), key)
+        .await?
+        .tasks;
+    let snoozed_task_ids = snooze::snoozed_task_ids(app_state, token, timezone).await?;
+    Ok(tasks
+        .iter()
+        .filter(|t| skip_task_ids.contains(&t.id) && !snoozed_task_ids.contains(&t.id))
+        .count())
+}
+
+/// Completes a subtask from the checklist while its parent stays on screen
+async fn complete_subtask(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    task_id: &str,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the subtask was already completed
+    if !user_state.tasks.iter().any(|t| t.id == task_id) {
+        return Ok(());
+    }
+    tasks::complete_task(token, task_id, test_server_url).await?;
+
+    let completed_task_ids = tasks::with_descendant_ids(&user_state.tasks, task_id);
+    let tasks = user_state
+        .tasks
+        .iter()
+        .filter(|t| !completed_task_ids.contains(&t.id))
+        .cloned()
+        .collect();
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(
+        key.to_string(),
+        UserState {
+            tasks,
+            ..user_state
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Adds the follow-up while the task being completed is still in the queue
+async fn follow_up(
+    app_state: &Arc<AppState>,
+    key: &str,
+    token: &str,
+    task_id: &str,
+    content: &str,
+    due: Option<&str>,
+    test_server_url: &Option<String>,
+) -> Result<(), Error> {
+    if content.trim().is_empty() {
+        return Ok(());
     }
+    let user_state = get_or_create_user_state(app_state.clone(), key).await?;
+    // Absent when the page is reloaded after the task was already completed
+    let Some(task) = user_state.tasks.iter().find(|t| t.id == task_id) else {
+        return Ok(());
+    };
+    let due = due.unwrap_or_default().trim();
+    tasks::add_follow_up(token, task, content.trim(), due, test_server_url).await?;
+    Ok(())
+}
+
+/// Stores the options that are given so that later requests keep them
+async fn update_options(
+    app_state: &Arc<AppState>,
+    key: &str,
+    user_state: UserState,
+    params: &HashMap<String, String>,
+) -> Result<UserState, Error> {
+    let order = params.get("order");
+    let hide_future = params.get("hide_future");
+    let children_first = params.get("children_first");
+    if order.is_none()
+        && hide_future.is_none()
+        && children_first.is_none()
+        && !params.contains_key("timezone")
+    {
+        return Ok(user_state);
+    }
+
+    let order = match order.map(String::as_str) {
+        Some("") => None,
+        Some(order) => Some(Order::from_str(order).map_err(|_| Error::BadRequest {
+            source: "update_options".to_string(),
+            message: format!("Unknown order: {order}"),
+        })?),
+        None => user_state.order,
+    };
+    // An explicit timezone replaces the one Todoist reports
+    let timezone = match params.get("timezone") {
+        Some(timezone) => {
+            Some(
+                time::timezone_from_str(timezone).map_err(|_| Error::BadRequest {
+                    source: "update_options".to_string(),
+                    message: format!("Unknown timezone: {timezone}"),
+                })?,
+            )
+        }
+        None => user_state.timezone,
+    };
+    let hide_future = match hide_future {
+        Some(hide_future) => hide_future == "on",
+        None => user_state.hide_future,
+    };
+    let children_first = match children_first {
+        Some(children_first) => children_first == "on",
+        None => user_state.children_first,
+    };
+
+    let user_state = UserState {
+        order,
+        hide_future,
+        children_first,
+        timezone,
+        ..user_state
+    };
+    let mut tx = app_state.db.begin(true).await;
+    tx.set(key.to_string(), user_state.clone())?;
+    tx.commit()?;
+
+    Ok(user_state)
 }
 
 async fn get_tasks(
     app_state: Arc<AppState>,
     token: &str,
     filter: &str,
-    timezone: &Tz,
+    timezone: &Timezone,
     complete_task_id: Option<&str>,
     skip_task_id: Option<&String>,
-    test_server_url: &Option<String>,
+    skip: SkipMode,
 ) -> Result<Vec<Task>, Error> {
     let key = format!("{token}{filter}");
+    let test_server_url = &app_state.test_server_url;
 
     let user_state = get_or_create_user_state(app_state.clone(), &key).await?;
-    let skip_task_ids = if let Some(task_id) = skip_task_id {
-        vec![task_id.to_string()]
-    } else {
-        Vec::new()
+    let skip_task_ids = merge_skip_task_ids(&user_state, skip_task_id);
+    // Skipped tasks moved to the end of the queue are still in it
+    let hidden_task_ids = match skip {
+        SkipMode::EndOfQueue => Vec::new(),
+        SkipMode::Session | SkipMode::Revisit => skip_task_ids.clone(),
     };
+    // Snoozes apply across filters, so they are kept per account rather than in the cache
+    let snoozed_task_ids = snooze::snoozed_task_ids(&app_state, token, timezone).await?;
 
     let db = &app_state.clone().db;
-    if has_cached_tasks(&user_state, timezone, complete_task_id, &skip_task_ids)? {
+    if has_cached_tasks(
+        &user_state,
+        timezone,
+        complete_task_id,
+        &hidden_task_ids,
+        &snoozed_task_ids,
+    )? {
         println!("CACHE HIT");
-        let skip_task_ids = merge_skip_task_ids(&user_state, skip_task_id);
-        let tasks =
-            filter_completed_task(user_state.tasks.clone(), complete_task_id, &skip_task_ids);
+        let tasks = filter_completed_task(user_state.tasks.clone(), complete_task_id, &[], &[]);
         let mut tx = db.begin(true).await;
         let user_state = UserState {
             tasks: tasks.clone(),
@@ -204,11 +930,18 @@ async fn get_tasks(
         tx.set(key.clone(), user_state)?;
         tx.commit()?;
 
+        // Skipped and snoozed tasks stay in the cache so that they can come back
+        let tasks = filter_completed_task(tasks, None, &hidden_task_ids, &snoozed_task_ids);
         Ok(markdown_to_html(tasks))
     } else {
         println!("CACHE EXPIRED OR NO TASKS");
         let tasks = tasks::all_tasks(token, filter, test_server_url).await?;
-        let tasks = filter_completed_task(tasks, complete_task_id, &skip_task_ids);
+        let tasks = filter_completed_task(tasks, complete_task_id, &[], &[]);
+        // Skips are kept across refreshes, for as long as the task is in the filter
+        let skip_task_ids = skip_task_ids
+            .into_iter()
+            .filter(|id| tasks.iter().any(|t| &t.id == id))
+            .collect();
         let mut tx = db.begin(true).await;
         let tasks_updated_at = time::now(timezone)?;
         let user_state = UserState {
@@ -220,11 +953,12 @@ async fn get_tasks(
         tx.set(key.clone(), user_state)?;
         tx.commit()?;
 
+        let tasks = filter_completed_task(tasks, None, &hidden_task_ids, &snoozed_task_ids);
         Ok(markdown_to_html(tasks))
     }
 }
 
-fn markdown_to_html(tasks: Vec<Task>) -> Vec<Task> {
+pub fn markdown_to_html(tasks: Vec<Task>) -> Vec<Task> {
     let options = Options::default();
     tasks
         .into_iter()
@@ -239,9 +973,11 @@ fn markdown_to_html(tasks: Vec<Task>) -> Vec<Task> {
     //            "<p>Hello, <strong>世界</strong>!</p>\n");
 }
 
+/// Skipped task ids in the order they were last skipped
 fn merge_skip_task_ids(user_state: &UserState, skip_task_id: Option<&String>) -> Vec<String> {
     if let Some(skip_task_id) = skip_task_id {
         let mut skip_task_ids = user_state.skip_task_ids.clone();
+        skip_task_ids.retain(|id| id != skip_task_id);
         skip_task_ids.push(skip_task_id.to_string());
         skip_task_ids
     } else {
@@ -251,13 +987,19 @@ fn merge_skip_task_ids(user_state: &UserState, skip_task_id: Option<&String>) ->
 
 fn has_cached_tasks(
     user_state: &UserState,
-    timezone: &Tz,
+    timezone: &Timezone,
     complete_task_id: Option<&str>,
     skip_task_ids: &[String],
+    snoozed_task_ids: &[String],
 ) -> Result<bool, Error> {
     if let Some(updated_at) = user_state.tasks_updated_at {
         let age = time::age_in_minutes(updated_at, timezone)?;
-        let more_tasks = more_tasks(user_state, complete_task_id, skip_task_ids);
+        let more_tasks = more_tasks(
+            user_state,
+            complete_task_id,
+            skip_task_ids,
+            snoozed_task_ids,
+        );
 
         if age < CACHE_TASKS_MAX_AGE_MINUTES && more_tasks {
             return Ok(true);
@@ -270,15 +1012,34 @@ fn filter_completed_task(
     tasks: Vec<Task>,
     complete_task_id: Option<&str>,
     skip_task_ids: &[String],
+    snoozed_task_ids: &[String],
 ) -> Vec<Task> {
+    // Completing a task in Todoist also completes its subtasks
+    let completed_task_ids = complete_task_id
+        .map(|task_id| tasks::with_descendant_ids(&tasks, task_id))
+        .unwrap_or_default();
     tasks
         .into_iter()
-        .filter(|t| t.id != complete_task_id.unwrap_or_default() && !skip_task_ids.contains(&t.id))
+        .filter(|t| {
+            !completed_task_ids.contains(&t.id)
+                && !skip_task_ids.contains(&t.id)
+                && !snoozed_task_ids.contains(&t.id)
+        })
         .collect::<Vec<Task>>()
 }
 
 /// Checks if there are more tasks to process (beyond the one that we are now completing)
-fn more_tasks(state: &UserState, complete_task_id: Option<&str>, skip_task_ids: &[String]) -> bool {
-    let tasks = filter_completed_task(state.tasks.clone(), complete_task_id, skip_task_ids);
+fn more_tasks(
+    state: &UserState,
+    complete_task_id: Option<&str>,
+    skip_task_ids: &[String],
+    snoozed_task_ids: &[String],
+) -> bool {
+    let tasks = filter_completed_task(
+        state.tasks.clone(),
+        complete_task_id,
+        skip_task_ids,
+        snoozed_task_ids,
+    );
     !tasks.is_empty()
 }
@@ -0,0 +1,62 @@
+use crate::account::{self, AccountState};
+use crate::error::Error;
+use crate::preferences::Preferences;
+use crate::unsplash;
+use crate::unsplash::Unsplash;
+use crate::views::process::fetch_parameter;
+use crate::{AppState, Link};
+use askama::Template;
+use axum::extract::State;
+use axum::{extract::Query, response::Html, routing::get, Router};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn routes(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/settings", get(settings))
+        .with_state(app_state)
+}
+
+#[derive(Template)]
+#[template(path = "settings.html")]
+struct SettingsTemplate {
+    title: String,
+    navigation: Vec<Link>,
+    token: String,
+    preferences: Preferences,
+    saved: bool,
+    unsplash: Unsplash,
+}
+
+/// Shows the preferences, or saves them when the form is submitted
+async fn settings(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Error> {
+    let token = fetch_parameter(&params, "token")?;
+    let account_state = account::get_or_create(&app_state, &token).await?;
+    let saved = params.contains_key("save");
+
+    let preferences = if saved {
+        let preferences = Preferences::from_params(&params)?;
+        let account_state = AccountState {
+            preferences: preferences.clone(),
+            ..account_state
+        };
+        account::save(&app_state, &token, account_state).await?;
+        preferences
+    } else {
+        account_state.preferences
+    };
+
+    let index = SettingsTemplate {
+        title: "Settings".into(),
+        navigation: crate::get_nav(),
+        token,
+        preferences,
+        saved,
+        unsplash: unsplash::stub(),
+    };
+
+    Ok(Html(index.render()?))
+}
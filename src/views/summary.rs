@@ -0,0 +1,43 @@
+use crate::error::Error;
+use crate::views::process::{fetch_parameter, get_or_create_user_state};
+use crate::AppState;
+use axum::extract::State;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::{extract::Query, routing::get, Router};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn routes(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/summary.md", get(export))
+        .with_state(app_state)
+}
+
+/// Downloads the latest session as Markdown
+async fn export(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, Error> {
+    let filter = fetch_parameter(&params, "filter")?;
+    let token = fetch_parameter(&params, "token")?;
+    let key = format!("{token}{filter}");
+    let user_state = get_or_create_user_state(app_state, &key).await?;
+    let session = user_state.session.ok_or_else(|| Error::BadRequest {
+        source: "export".to_string(),
+        message: "There is no session to export".to_string(),
+    })?;
+    let markdown = session.to_markdown(&session.started_at.timezone())?;
+    let filename = format!(
+        "attachment; filename=\"singletask-{}.md\"",
+        session.started_at.format("%Y-%m-%d")
+    );
+
+    Ok((
+        [
+            (CONTENT_TYPE, "text/markdown; charset=utf-8".to_string()),
+            (CONTENT_DISPOSITION, filename),
+        ],
+        markdown,
+    ))
+}
@@ -1,4 +1,29 @@
+// Shortcuts should not fire while typing
+const isTyping = (event) => ['INPUT', 'TEXTAREA', 'SELECT'].includes(event.target.tagName);
+
 document.addEventListener('DOMContentLoaded', () => {
+  // Actions are one-off, so keep only the token and filter in the address for reloads
+  const url = new URL(window.location.href);
+  if (url.pathname === '/process') {
+    for (const key of Array.from(url.searchParams.keys())) {
+      if (!['token', 'filter'].includes(key)) {
+        url.searchParams.delete(key);
+      }
+    }
+    window.history.replaceState(null, '', url);
+  }
+  // Select the form element
+  const captureform = document.getElementById('captureform');
+
+  if (captureform) {
+    // Check if the pressed key is 'q'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'q' || event.key === 'Q')) {
+        event.preventDefault();
+        document.getElementById('capture').focus();
+      }
+    });
+  }
   // Select the form element
   const completeform = document.getElementById('completeform');
 
@@ -6,7 +31,7 @@ document.addEventListener('DOMContentLoaded', () => {
     // Add event listener for keydown events
     document.addEventListener('keydown', (event) => {
       // Check if the pressed key is 'c'
-      if (event.key === 'c' || event.key === 'C') {
+      if (!isTyping(event) && (event.key === 'c' || event.key === 'C')) {
         // Prevent the default behavior
         event.preventDefault();
         // Submit the form
@@ -20,8 +45,8 @@ document.addEventListener('DOMContentLoaded', () => {
   if (skipform) {
     // Add event listener for keydown events
     document.addEventListener('keydown', (event) => {
-      // Check if the pressed key is 'c'
-      if (event.key === 's' || event.key === 'S') {
+      // Check if the pressed key is 's'
+      if (!isTyping(event) && (event.key === 's' || event.key === 'S')) {
         // Prevent the default behavior
         event.preventDefault();
         // Submit the form
@@ -29,4 +54,156 @@ document.addEventListener('DOMContentLoaded', () => {
       }
     });
   }
+  // Select the form element
+  const commentform = document.getElementById('commentform');
+
+  if (commentform) {
+    const comment = document.getElementById('comment');
+    // Check if the pressed key is 'n'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'n' || event.key === 'N')) {
+        event.preventDefault();
+        comment.focus();
+      }
+    });
+    // Ctrl+Enter or Cmd+Enter posts the comment
+    comment.addEventListener('keydown', (event) => {
+      if (event.key === 'Enter' && (event.ctrlKey || event.metaKey)) {
+        event.preventDefault();
+        commentform.submit();
+      }
+    });
+  }
+  // Select the details element
+  const followupdetails = document.getElementById('followupdetails');
+
+  if (followupdetails) {
+    // Check if the pressed key is 'f'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'f' || event.key === 'F')) {
+        event.preventDefault();
+        followupdetails.open = true;
+        document.getElementById('follow_up').focus();
+      }
+    });
+  }
+  // Select the details element
+  const editdetails = document.getElementById('editdetails');
+
+  if (editdetails) {
+    // Check if the pressed key is 'e'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'e' || event.key === 'E')) {
+        event.preventDefault();
+        editdetails.open = true;
+        document.getElementById('content').focus();
+      }
+    });
+  }
+  // Select the details element
+  const movedetails = document.getElementById('movedetails');
+
+  if (movedetails) {
+    const movesearch = document.getElementById('movesearch');
+    const moveTo = document.getElementById('move_to');
+    const visible = () => Array.from(moveTo.options).filter((option) => !option.hidden);
+    // Check if the pressed key is 'm'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'm' || event.key === 'M')) {
+        event.preventDefault();
+        movedetails.open = true;
+        movesearch.focus();
+      }
+    });
+    // Narrow the choices down as the user types
+    movesearch.addEventListener('input', () => {
+      const query = movesearch.value.toLowerCase();
+      for (const option of moveTo.options) {
+        option.hidden = !option.text.toLowerCase().includes(query);
+      }
+      const [first] = visible();
+      moveTo.value = first ? first.value : '';
+    });
+    // Enter moves to the first match
+    movesearch.addEventListener('keydown', (event) => {
+      if (event.key === 'Enter') {
+        event.preventDefault();
+        if (moveTo.value) {
+          document.getElementById('moveform').submit();
+        }
+      }
+    });
+  }
+  // Select the details element
+  const deletedetails = document.getElementById('deletedetails');
+
+  if (deletedetails) {
+    // Pressing 'd' once asks for confirmation, pressing it again deletes
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'd' || event.key === 'D')) {
+        event.preventDefault();
+        if (deletedetails.open) {
+          document.getElementById('deleteform').submit();
+        } else {
+          deletedetails.open = true;
+        }
+      }
+    });
+    // Deleting from the skip alert asks for the same confirmation
+    const skipalertdelete = document.getElementById('skipalertdelete');
+
+    if (skipalertdelete) {
+      skipalertdelete.addEventListener('click', () => {
+        deletedetails.open = true;
+        deletedetails.scrollIntoView({ behavior: 'smooth' });
+      });
+    }
+  }
+  // Select the form element
+  const undoform = document.getElementById('undoform');
+
+  if (undoform) {
+    // Check if the pressed key is 'u'
+    document.addEventListener('keydown', (event) => {
+      if (!isTyping(event) && (event.key === 'u' || event.key === 'U')) {
+        event.preventDefault();
+        undoform.submit();
+      }
+    });
+  }
+  // Ticking a subtask completes it
+  for (const subtaskform of document.querySelectorAll('.subtaskform')) {
+    subtaskform.querySelector('input[type="checkbox"]').addEventListener('change', () => {
+      subtaskform.submit();
+    });
+  }
+  // Time since the task was first shown
+  const elapsed = document.getElementById('elapsed');
+
+  if (elapsed) {
+    const shownAt = Number(elapsed.dataset.shownAt);
+    const tick = () => {
+      const seconds = Math.max(0, Math.floor(Date.now() / 1000) - shownAt);
+      const minutes = Math.floor(seconds / 60);
+      elapsed.textContent = `${minutes}:${String(seconds % 60).padStart(2, '0')}`;
+    };
+    tick();
+    setInterval(tick, 1000);
+  }
+  // Pomodoro countdown, reloads so that the server can move on to the next phase
+  const countdown = document.getElementById('countdown');
+
+  if (countdown) {
+    const endsAt = Number(countdown.dataset.endsAt);
+    const tick = () => {
+      const seconds = endsAt - Math.floor(Date.now() / 1000);
+      if (seconds <= 0) {
+        window.location.reload();
+        return;
+      }
+      countdown.textContent = `${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, '0')}`;
+    };
+    tick();
+    setInterval(tick, 1000);
+  }
 });